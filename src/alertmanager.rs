@@ -0,0 +1,138 @@
+use crate::body_type::{summary, Embed, EmbedAuthor, EmbedData, EmbedField, MessageKey};
+use crate::body_type::MAX_FIELDS;
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const RED: u32 = 0xe6522c;
+const GREEN: u32 = 0x2ea44f;
+
+/// The most alerts listed in a message, the rest are only counted
+const MAX_ALERTS: usize = 10;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    status: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+    #[serde(default)]
+    generator_url: String,
+}
+
+/// Alertmanager's webhook payload, version 4
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertmanagerWebhook {
+    group_key: String,
+    #[serde(default)]
+    truncated_alerts: u64,
+    status: String,
+    receiver: String,
+    #[serde(default)]
+    common_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    common_annotations: BTreeMap<String, String>,
+    #[serde(rename = "externalURL", default)]
+    external_url: String,
+    alerts: Vec<Alert>,
+}
+
+impl AlertmanagerWebhook {
+    fn is_resolved(&self) -> bool { self.status == "resolved" }
+
+    fn render(&self) -> EmbedData {
+        let firing = self.alerts.iter().filter(|a| a.status == "firing").count();
+        let alert_name = self
+            .common_labels
+            .get("alertname")
+            .map(String::as_str)
+            .unwrap_or("Alert group");
+        let title = if self.is_resolved() {
+            format!("[RESOLVED] {alert_name}")
+        } else {
+            format!("[FIRING:{firing}] {alert_name}")
+        };
+        let mut description = self
+            .alerts
+            .iter()
+            .take(MAX_ALERTS)
+            .map(|alert| {
+                let name = alert
+                    .annotations
+                    .get("summary")
+                    .or_else(|| alert.labels.get("instance"))
+                    .or_else(|| alert.labels.get("alertname"))
+                    .map(String::as_str)
+                    .unwrap_or("alert");
+                let marker = if alert.status == "firing" { "🔥" } else { "✅" };
+                if alert.generator_url.is_empty() {
+                    format!("{marker} {name}")
+                } else {
+                    format!("{marker} [{name}]({})", alert.generator_url)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        let more = self.alerts.len().saturating_sub(MAX_ALERTS) as u64 + self.truncated_alerts;
+        if more > 0 {
+            description.push_str(&format!("\n...and {more} more"));
+        }
+        let fields = self
+            .common_labels
+            .iter()
+            .filter(|(name, _)| name.as_str() != "alertname")
+            .map(|(name, value)| EmbedField::new(name, value, true))
+            .chain(
+                self.common_annotations
+                    .iter()
+                    .map(|(name, value)| EmbedField::new(name, &summary(Some(value)), false)),
+            )
+            .take(MAX_FIELDS)
+            .collect();
+        EmbedData::new(
+            &title,
+            &description,
+            &self.external_url,
+            if self.is_resolved() { GREEN } else { RED },
+            &format!("Alertmanager - {}", self.receiver),
+            EmbedAuthor::new("Alertmanager", &self.external_url, ""),
+            fields,
+        )
+    }
+}
+
+impl Embed for AlertmanagerWebhook {
+    fn get_username(&self) -> String { self.receiver.clone() }
+
+    fn get_avatar_url(&self) -> String { "".into() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { vec![self.render()] }
+
+    fn get_message_key(&self) -> Option<MessageKey> {
+        Some(MessageKey::new(&self.group_key, self.is_resolved()))
+    }
+}
+
+/// Prometheus Alertmanager webhooks, authenticated with the app token
+pub struct Alertmanager;
+
+impl Source for Alertmanager {
+    fn name(&self) -> &'static str { "alertmanager" }
+
+    fn detect(&self, request: &Incoming) -> bool {
+        request
+            .header("User-Agent")
+            .map(|agent| agent.starts_with("Alertmanager/"))
+            .unwrap_or(false)
+            || serde_json::from_slice::<AlertmanagerWebhook>(request.body).is_ok()
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let webhook: AlertmanagerWebhook = serde_json::from_slice(request.body)?;
+        Ok(Some(Box::new(webhook)))
+    }
+}
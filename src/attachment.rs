@@ -0,0 +1,120 @@
+use crate::body_type::DiscordWebhook;
+use axum::{
+    async_trait,
+    body::{Body, Bytes, HttpBody},
+    extract::{FromRequest, Json, Multipart, RequestParts},
+    http::{header::CONTENT_TYPE, HeaderMap, Request, StatusCode},
+    response::IntoResponse,
+    BoxError,
+};
+use futures_util::StreamExt;
+use serde::de::DeserializeOwned;
+
+/// The default total size of attachments accepted with a single webhook, 8MiB
+const DEFAULT_MAX_ATTACHMENT_SIZE: usize = 8 * 1024 * 1024;
+
+/// The total size of attachments accepted with a single webhook or email
+pub fn max_attachment_size() -> usize {
+    std::env::var("MAX_ATTACHMENT_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_MAX_ATTACHMENT_SIZE)
+}
+
+/// Room left in a request body for the webhook itself and multipart framing, 1MiB
+const BODY_HEADROOM: usize = 1024 * 1024;
+
+/// The largest request body read before it's rejected
+pub fn max_body_size() -> usize {
+    max_attachment_size().saturating_add(BODY_HEADROOM)
+}
+
+/// A file uploaded alongside a webhook, held in memory until the bot re-uploads it
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub(crate) filename: String,
+    pub(crate) data: Vec<u8>,
+}
+
+/// A Discord webhook body sent either as JSON or as `multipart/form-data`
+///
+/// Multipart bodies carry the webhook in `payload_json` and any files in `files[n]`,
+/// matching Discord's own execute and edit webhook message endpoints
+pub struct DiscordUpload<T = DiscordWebhook> {
+    pub(crate) webhook: T,
+    pub(crate) attachments: Vec<Attachment>,
+}
+
+impl<T: DeserializeOwned + Send> DiscordUpload<T> {
+    /// Parse a body that has already been read, once its signature has been checked
+    pub async fn from_body(headers: &HeaderMap, body: Bytes) -> Result<Self, StatusCode> {
+        let mut request = Request::new(Body::from(body));
+        *request.headers_mut() = headers.clone();
+        Self::from_request(&mut RequestParts::new(request)).await
+    }
+}
+
+#[async_trait]
+impl<B, T> FromRequest<B> for DiscordUpload<T>
+where
+    B: HttpBody<Data = Bytes> + Default + Unpin + Send + 'static,
+    B::Error: Into<BoxError>,
+    T: DeserializeOwned + Send,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let is_multipart = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.starts_with("multipart/form-data"))
+            .unwrap_or(false);
+        if !is_multipart {
+            let Json(webhook) = Json::<T>::from_request(req)
+                .await
+                .map_err(|rejection| rejection.into_response().status())?;
+            return Ok(DiscordUpload {
+                webhook,
+                attachments: vec![],
+            });
+        }
+        let limit = max_attachment_size();
+        let mut multipart = Multipart::from_request(req)
+            .await
+            .map_err(|_| StatusCode::BAD_REQUEST)?;
+        let mut webhook = None;
+        let mut attachments = vec![];
+        let mut total = 0;
+        while let Some(mut field) = multipart
+            .next_field()
+            .await
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+        {
+            let name = field.name().unwrap_or_default().to_string();
+            let filename = field.file_name().map(String::from);
+            let mut data = vec![];
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(|_| StatusCode::BAD_REQUEST)?;
+                total += chunk.len();
+                if total > limit {
+                    return Err(StatusCode::PAYLOAD_TOO_LARGE);
+                }
+                data.extend_from_slice(&chunk);
+            }
+            if name == "payload_json" {
+                webhook =
+                    Some(serde_json::from_slice(&data).map_err(|_| StatusCode::BAD_REQUEST)?);
+            } else if let Some(filename) = filename {
+                attachments.push(Attachment { filename, data });
+            }
+        }
+        match webhook {
+            Some(webhook) => Ok(DiscordUpload {
+                webhook,
+                attachments,
+            }),
+            None => Err(StatusCode::BAD_REQUEST),
+        }
+    }
+}
@@ -1,13 +1,115 @@
+use crate::attachment::Attachment;
 use serde::{Deserialize, Serialize};
+use serenity::model::channel::Message;
+use tokio::sync::oneshot;
 
 pub trait Embed {
     fn get_username(&self) -> String;
     fn get_avatar_url(&self) -> String;
-    fn get_first_embed(&self) -> EmbedData;
     fn get_embeds(&self) -> Vec<EmbedData>;
+    /// A key for payloads that should edit an earlier message instead of posting a new one
+    fn get_message_key(&self) -> Option<MessageKey> { None }
+    /// The full message to post, sources with more than embeds can override this
+    fn get_message(&self) -> MessageData { MessageData::from_embeds(self.get_embeds()) }
 }
 
-#[derive(Debug)]
+/// Sent back to a route waiting on delivery, `None` if the bot failed to post the message
+pub type Reply = oneshot::Sender<Option<Message>>;
+
+/// A message queued for the bot to post
+pub type Delivery = (Destination, MessageData, Option<Reply>);
+
+/// Discord allows at most 10 embeds in a single message
+pub const MAX_EMBEDS: usize = 10;
+
+/// Discord rejects embeds with more than 25 fields
+pub const MAX_FIELDS: usize = 25;
+
+/// Discord's limits on each part of an embed, in characters
+const MAX_TITLE: usize = 256;
+const MAX_DESCRIPTION: usize = 4096;
+const MAX_FIELD_NAME: usize = 256;
+const MAX_FIELD_VALUE: usize = 1024;
+const MAX_FOOTER: usize = 2048;
+const MAX_AUTHOR_NAME: usize = 256;
+
+/// Discord's limit on the text of every embed in a message put together
+pub const MAX_EMBEDS_SIZE: usize = 6000;
+
+/// Everything that goes into one message posted in an apps thread
+#[derive(Debug, Clone, Default)]
+pub struct MessageData {
+    pub(crate) content: String,
+    pub(crate) tts: bool,
+    pub(crate) embeds: Vec<EmbedData>,
+    pub(crate) allowed_mentions: Option<AllowedMentions>,
+    pub(crate) attachments: Vec<Attachment>,
+}
+
+impl MessageData {
+    pub fn from_embeds(embeds: Vec<EmbedData>) -> MessageData {
+        MessageData {
+            embeds: embeds.into_iter().take(MAX_EMBEDS).collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.content.trim().is_empty() && self.embeds.is_empty() && self.attachments.is_empty()
+    }
+}
+
+/// Split embeds into groups that each fit in one message, cutting them down to Discord's limits
+pub fn chunk_embeds(embeds: Vec<EmbedData>) -> Vec<Vec<EmbedData>> {
+    let mut chunks: Vec<Vec<EmbedData>> = vec![];
+    let mut size = 0;
+    for embed in embeds.iter().map(EmbedData::truncated) {
+        let embed_size = embed.size();
+        match chunks.last_mut() {
+            Some(chunk) if chunk.len() < MAX_EMBEDS && size + embed_size <= MAX_EMBEDS_SIZE => {
+                size += embed_size;
+                chunk.push(embed);
+            }
+            _ => {
+                size = embed_size;
+                chunks.push(vec![embed]);
+            }
+        }
+    }
+    chunks
+}
+
+/// Discord's allowed mentions object, ids are snowflake strings
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AllowedMentions {
+    #[serde(default)]
+    pub(crate) parse: Vec<String>,
+    #[serde(default)]
+    pub(crate) users: Vec<String>,
+    #[serde(default)]
+    pub(crate) roles: Vec<String>,
+    #[serde(default)]
+    pub(crate) replied_user: bool,
+}
+
+/// Identifies a message that later payloads with the same key will edit
+#[derive(Debug, Clone)]
+pub struct MessageKey {
+    pub(crate) key: String,
+    /// Whether this is the last update, the next payload with this key gets a new message
+    pub(crate) done: bool,
+}
+
+impl MessageKey {
+    pub fn new(key: &str, done: bool) -> MessageKey {
+        MessageKey {
+            key: key.into(),
+            done,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Destination {
     pub(crate) username: String,
     pub(crate) _avatar_url: String,
@@ -15,6 +117,7 @@ pub struct Destination {
     pub(crate) channel_id: u64,
     pub(crate) user_id: u64,
     pub(crate) app_id: u64,
+    pub(crate) message_key: Option<MessageKey>,
 }
 
 impl Destination {
@@ -33,31 +136,158 @@ impl Destination {
             channel_id,
             user_id,
             app_id,
+            message_key: None,
         }
     }
+
+    pub fn with_message_key(mut self, message_key: Option<MessageKey>) -> Destination {
+        self.message_key = message_key;
+        self
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A Discord embed, every part of it is optional just like in Discord's own schema
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct EmbedData {
-    pub(crate) title: String,
-    pub(crate) description: String,
-    pub(crate) url: String,
-    pub(crate) color: u32,
-    pub(crate) footer: EmbedFooter,
-    pub(crate) author: EmbedAuthor,
+    pub(crate) title: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) url: Option<String>,
+    /// ISO8601 timestamp shown next to the footer
+    pub(crate) timestamp: Option<String>,
+    pub(crate) color: Option<u32>,
+    pub(crate) footer: Option<EmbedFooter>,
+    pub(crate) image: Option<EmbedMedia>,
+    pub(crate) thumbnail: Option<EmbedMedia>,
+    pub(crate) video: Option<EmbedMedia>,
+    pub(crate) provider: Option<EmbedProvider>,
+    pub(crate) author: Option<EmbedAuthor>,
     pub(crate) fields: Option<Vec<EmbedField>>,
 }
 
+impl EmbedData {
+    pub fn new(
+        title: &str,
+        description: &str,
+        url: &str,
+        color: u32,
+        footer: &str,
+        author: EmbedAuthor,
+        fields: Vec<EmbedField>,
+    ) -> EmbedData {
+        EmbedData {
+            title: non_empty(title),
+            description: non_empty(description),
+            url: non_empty(url),
+            color: Some(color),
+            footer: non_empty(footer).map(|text| EmbedFooter {
+                text,
+                icon_url: None,
+            }),
+            author: Some(author),
+            fields: if fields.is_empty() { None } else { Some(fields) },
+            ..Default::default()
+        }
+    }
+
+    /// A copy cut down to Discord's limits on each part
+    pub fn truncated(&self) -> EmbedData {
+        let mut embed = self.clone();
+        embed.title = embed.title.map(|title| truncate(&title, MAX_TITLE));
+        embed.description = embed
+            .description
+            .map(|description| truncate(&description, MAX_DESCRIPTION));
+        if let Some(footer) = &mut embed.footer {
+            footer.text = truncate(&footer.text, MAX_FOOTER);
+        }
+        if let Some(author) = &mut embed.author {
+            author.name = truncate(&author.name, MAX_AUTHOR_NAME);
+        }
+        if let Some(fields) = &mut embed.fields {
+            fields.truncate(MAX_FIELDS);
+            for field in fields {
+                field.name = truncate(&field.name, MAX_FIELD_NAME);
+                field.value = truncate(&field.value, MAX_FIELD_VALUE);
+            }
+        }
+        // A single embed can still be over the total, so drop fields and then cut the description
+        while embed.size() > MAX_EMBEDS_SIZE {
+            match &mut embed.fields {
+                Some(fields) if !fields.is_empty() => {
+                    fields.pop();
+                }
+                _ => break,
+            }
+        }
+        let over = embed.size().saturating_sub(MAX_EMBEDS_SIZE);
+        if let Some(description) = embed.description.as_mut().filter(|_| over > 0) {
+            let length = description.chars().count().saturating_sub(over);
+            *description = truncate(description, length);
+        }
+        embed
+    }
+
+    /// How many characters Discord counts towards [`MAX_EMBEDS_SIZE`] once it's truncated
+    pub fn size(&self) -> usize {
+        let count = |text: &str, max: usize| text.chars().count().min(max);
+        let fields = self.fields.iter().flatten().take(MAX_FIELDS).map(|field| {
+            count(&field.name, MAX_FIELD_NAME) + count(&field.value, MAX_FIELD_VALUE)
+        });
+        self.title.as_deref().map_or(0, |title| count(title, MAX_TITLE))
+            + self
+                .description
+                .as_deref()
+                .map_or(0, |description| count(description, MAX_DESCRIPTION))
+            + self.footer.as_ref().map_or(0, |footer| count(&footer.text, MAX_FOOTER))
+            + self.author.as_ref().map_or(0, |author| count(&author.name, MAX_AUTHOR_NAME))
+            + fields.sum::<usize>()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EmbedFooter {
     pub(crate) text: String,
+    pub(crate) icon_url: Option<String>,
+}
+
+/// An image, thumbnail or video in an embed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmbedMedia {
+    pub(crate) url: String,
+    pub(crate) height: Option<u32>,
+    pub(crate) width: Option<u32>,
+}
+
+impl EmbedMedia {
+    pub fn new(url: &str) -> EmbedMedia {
+        EmbedMedia {
+            url: url.into(),
+            height: None,
+            width: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmbedProvider {
+    pub(crate) name: Option<String>,
+    pub(crate) url: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct EmbedAuthor {
     pub(crate) name: String,
-    pub(crate) url: String,
-    pub(crate) icon_url: String,
+    pub(crate) url: Option<String>,
+    pub(crate) icon_url: Option<String>,
+}
+
+impl EmbedAuthor {
+    pub fn new(name: &str, url: &str, icon_url: &str) -> EmbedAuthor {
+        EmbedAuthor {
+            name: name.into(),
+            url: non_empty(url),
+            icon_url: non_empty(icon_url),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -67,15 +297,106 @@ pub struct EmbedField {
     pub(crate) inline: Option<bool>,
 }
 
+impl EmbedField {
+    pub fn new(name: &str, value: &str, inline: bool) -> EmbedField {
+        EmbedField {
+            name: name.into(),
+            value: value.into(),
+            inline: Some(inline),
+        }
+    }
+}
+
+/// Discord rejects empty strings for most embed parts, so leave them out instead
+pub fn non_empty(input: &str) -> Option<String> {
+    if input.is_empty() {
+        None
+    } else {
+        Some(input.into())
+    }
+}
+
+/// Cut text down to `max` characters, ending it with `...` when anything was cut
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() > max {
+        format!("{}...", text.chars().take(max.saturating_sub(3)).collect::<String>())
+    } else {
+        text.into()
+    }
+}
+
+/// Strip `refs/heads/` or `refs/tags/` from a git ref
+pub fn short_ref(git_ref: &str) -> &str {
+    git_ref
+        .strip_prefix("refs/heads/")
+        .or_else(|| git_ref.strip_prefix("refs/tags/"))
+        .unwrap_or(git_ref)
+}
+
+/// The most commits listed in a push embed before the rest are summarised
+const MAX_COMMITS: usize = 10;
+
+/// A commit in a push payload, GitHub, Gitea and GitLab all send the same fields
+#[derive(Deserialize, Debug)]
+pub struct Commit {
+    id: String,
+    message: String,
+    url: String,
+    author: CommitAuthor,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommitAuthor {
+    name: String,
+}
+
+/// List the commits of a push, one line each with a `sha_len` long sha
+///
+/// `total` can be more than the commits sent, GitLab only sends the first 20
+pub fn commit_list(commits: &[Commit], total: usize, sha_len: usize) -> String {
+    let mut list = commits
+        .iter()
+        .take(MAX_COMMITS)
+        .map(|commit| {
+            format!(
+                "[`{}`]({}) {} - {}",
+                commit.id.chars().take(sha_len).collect::<String>(),
+                commit.url,
+                commit.message.lines().next().unwrap_or(""),
+                commit.author.name
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    if total > MAX_COMMITS {
+        list.push_str(&format!("\n...and {} more", total - MAX_COMMITS));
+    }
+    list
+}
+
+/// Shorten a markdown body so it fits comfortably in an embed description
+pub fn summary(body: Option<&str>) -> String {
+    let body = body.unwrap_or("").trim();
+    if body.chars().count() > 500 {
+        format!("{}...", body.chars().take(500).collect::<String>())
+    } else {
+        body.into()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DiscordWebhook {
-    wait: bool,
+    #[serde(default)]
     content: String,
+    #[serde(default)]
     username: String,
-    #[serde(rename(deserialize = "avatar_url"))]
+    #[serde(rename(deserialize = "avatar_url"), default)]
     _avatar_url: String,
+    #[serde(default)]
     tts: bool,
+    #[serde(default)]
     embeds: Vec<EmbedData>,
+    allowed_mentions: Option<AllowedMentions>,
 }
 
 impl Embed for DiscordWebhook {
@@ -83,7 +404,22 @@ impl Embed for DiscordWebhook {
 
     fn get_avatar_url(&self) -> String { self._avatar_url.clone() }
 
-    fn get_first_embed(&self) -> EmbedData { self.embeds[0].clone() }
-
     fn get_embeds(&self) -> Vec<EmbedData> { self.embeds.clone() }
+
+    fn get_message(&self) -> MessageData {
+        MessageData {
+            content: self.content.clone(),
+            tts: self.tts,
+            allowed_mentions: self.allowed_mentions.clone(),
+            ..MessageData::from_embeds(self.get_embeds())
+        }
+    }
+}
+
+/// The body of a webhook message edit, anything left out is kept as it was
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DiscordMessageEdit {
+    pub(crate) content: Option<String>,
+    pub(crate) embeds: Option<Vec<EmbedData>>,
+    pub(crate) allowed_mentions: Option<AllowedMentions>,
 }
@@ -1,30 +1,54 @@
-use crate::body_type::{Destination, EmbedData};
-use crate::{AppCollection, UserCollection};
-use bcrypt::{hash, verify, DEFAULT_COST};
+use crate::attachment::Attachment;
+use crate::body_type::{
+    AllowedMentions, Delivery, Destination, DiscordMessageEdit, EmbedData, MessageData, MAX_EMBEDS,
+    MAX_EMBEDS_SIZE,
+};
+use crate::{feed, heartbeat, token};
+use crate::signature::SignatureScheme;
+use crate::template::EmbedTemplate;
+use crate::{AppCollection, AppToken, FeedCollection, MessageCollection, PostedMessageCollection};
+use crate::UserCollection;
 use mongodb::{
     bson::oid::ObjectId,
-    bson::{doc, Bson},
+    bson::{doc, to_bson, Bson, DateTime},
+    options::UpdateOptions,
     Database,
 };
 use serenity::client::{Context, EventHandler};
-use serenity::model::{channel::Message, gateway::Ready, id::ChannelId};
+use serenity::model::{
+    channel::{AttachmentType, Message},
+    gateway::Ready,
+    id::{ChannelId, GuildId, MessageId},
+    Timestamp,
+};
+use serde_json::json;
 use serenity::{async_trait, model::id::RoleId};
-use serenity::{builder::CreateMessage, model::user::User, prelude::*};
+use serenity::{
+    builder::{CreateAllowedMentions, CreateEmbed, CreateMessage, EditMessage, ParseValue},
+    http::Http,
+    model::user::User,
+    prelude::*,
+};
+use futures_util::TryStreamExt;
+use std::borrow::Cow;
 use std::sync::Arc;
 use tokio::sync::mpsc::Receiver;
 use tokio::sync::RwLock;
 use yyid::*;
 
+/// How long an old token keeps working after `rotate` when no grace period is given, in seconds
+const DEFAULT_ROTATE_GRACE: u64 = 24 * 60 * 60;
+
 pub(crate) struct Handler {
     prefix: char,
-    incoming_embed: Arc<RwLock<Receiver<(Destination, EmbedData)>>>,
+    incoming_embed: Arc<RwLock<Receiver<Delivery>>>,
     db: Database,
 }
 
 impl Handler {
     pub fn new(
         prefix: char,
-        receiver: Receiver<(Destination, EmbedData)>,
+        receiver: Receiver<Delivery>,
         db: Database,
     ) -> Handler {
         Handler {
@@ -53,6 +77,15 @@ impl EventHandler for Handler {
             "request" => request(&self.prefix, &self.db, parameters, &ctx, &msg).await,
             "approve" => approve(&self.db, parameters, &ctx, &msg).await,
             "revoke" => revoke(&self.db, parameters, &ctx, &msg).await,
+            "secret" => secret(&self.db, parameters, &ctx, &msg).await,
+            "template" => template(&self.db, parameters, &ctx, &msg).await,
+            "feed" => feed(&self.db, parameters, &ctx, &msg).await,
+            "heartbeat" => heartbeat(&self.db, parameters, &ctx, &msg).await,
+            "headeronly" => header_only(&self.db, parameters, &ctx, &msg).await,
+            "signature" => signature(&self.db, parameters, &ctx, &msg).await,
+            "rotate" => rotate(&self.db, parameters, &ctx, &msg).await,
+            "token" => tokens(&self.db, parameters, &ctx, &msg).await,
+            "info" => info(&self.db, parameters, &ctx, &msg).await,
             "help" => help(&self.prefix, &ctx, &msg).await,
             _ => {}
         }
@@ -60,7 +93,8 @@ impl EventHandler for Handler {
 
     async fn ready(&self, ctx: Context, ready: Ready) {
         let mut receiver = self.incoming_embed.write().await;
-        'receive: while let Some((dest, embed)) = receiver.recv().await {
+        while let Some((dest, message, reply)) = receiver.recv().await {
+            let mut posted = None;
             for guild in &ready.guilds {
                 let guild = guild.id;
                 if dest.server_id != guild.0 {
@@ -71,100 +105,321 @@ impl EventHandler for Handler {
                     .get_user(dest.user_id)
                     .await
                     .expect("Failed to get user");
-                let mut message = CreateMessage::default();
-                message.embed(|e| {
-                    e.title(escape(&embed.title.clone()))
-                        .author(|a| {
-                            a.name(escape(&embed.author.name))
-                                .icon_url(&embed.author.icon_url)
-                                .url(&embed.author.url)
-                        })
-                        .description(escape(&embed.description))
-                        .url(escape(&embed.url))
-                        .fields({
-                            let mut fields: Vec<(String, String, bool)> = vec![];
-                            if let Some(e_fields) = &embed.fields {
-                                for field in e_fields {
-                                    fields.push((
-                                        escape(&field.name),
-                                        escape(&field.value),
-                                        field.inline.unwrap_or(false),
-                                    ));
-                                }
-                            }
-                            fields
-                        })
-                        .footer(|f| f.text(&embed.footer.text))
-                });
-                // Check to see if a thread already exists for this application id
-                if let Ok(threadsdata) = &ctx.http.get_guild_active_threads(guild.0).await {
-                    let threads = &threadsdata.threads;
-                    for thread in threads {
-                        if let Ok(messages) = &mut ctx.http.get_messages(thread.id.0, "").await {
-                            // Reverse the messages because they are listed from last to first
-                            messages.reverse();
-                            let mut messages = messages.iter();
-                            // Skip the first one
-                            messages.next().unwrap();
-                            if let Some(id_message) = messages.next() {
-                                if id_message.content == format!("{:#}", &dest.app_id) {
-                                    if thread.name()
-                                        != format!(
-                                            "{} - {}",
-                                            escape(&dest.username),
-                                            escape(&user.name)
-                                        )
-                                    {
-                                        continue;
-                                    }
-                                    let mut message_clone = message.clone();
-                                    thread
-                                        .send_message(&ctx.http, |_m| &mut message_clone)
-                                        .await
-                                        .expect("Failed to send embed");
-                                    continue 'receive;
-                                }
-                            }
+                if let Some(thread) = get_app_thread(&ctx, guild, &dest, user).await {
+                    match deliver(&ctx, &self.db, thread, &dest, &message).await {
+                        Ok(message) => {
+                            record_posted(&self.db, dest.app_id, &message).await;
+                            posted = Some(message);
                         }
+                        Err(e) => eprintln!("Failed to send embed: {}", e),
                     }
                 }
-                // Create a thread for the applicaiton id if one doesn't exist
-                if let Ok(channels) = &guild.channels(&ctx.http).await {
-                    if let Some(channel) = channels.get(&ChannelId(dest.channel_id)) {
-                        let start_message = channel
-                            .send_message(&ctx.http, |m| {
-                                m.content(format!(
-                                    "{} - {}",
-                                    escape(&dest.username),
-                                    escape(&user.name)
-                                ))
-                            })
-                            .await
-                            .expect("Failed to create ID Message");
-                        let thread = channel
-                            .create_public_thread(&ctx.http, start_message.id, |thread| {
-                                thread.name(format!(
-                                    "{} - {}",
-                                    escape(&dest.username),
-                                    escape(&user.name)
-                                ))
-                            })
-                            .await
-                            .expect("Failed to create public thread");
-                        thread
-                            .send_message(&ctx.http, |m| m.content(format!("{:#}", &dest.app_id)))
-                            .await
-                            .expect("Failed to create ID Message");
-                        let mut message_clone = message.clone();
-                        thread
-                            .send_message(&ctx.http, |_m| &mut message_clone)
-                            .await
-                            .expect("Failed to send embed");
+                break;
+            }
+            if let Some(reply) = reply {
+                // The route may have stopped waiting, which is fine
+                let _ = reply.send(posted);
+            }
+        }
+    }
+}
+
+/// Build a message's embeds, dropping any past Discord's limit on their total size
+fn create_embeds(embeds: &[EmbedData]) -> Vec<CreateEmbed> {
+    let mut total = 0;
+    embeds
+        .iter()
+        .take(MAX_EMBEDS)
+        .map(EmbedData::truncated)
+        .take_while(|embed| {
+            total += embed.size();
+            total <= MAX_EMBEDS_SIZE
+        })
+        .map(|embed| create_embed(&embed))
+        .collect()
+}
+
+/// Build an embed, cutting each part down to Discord's limits
+fn create_embed(embed: &EmbedData) -> CreateEmbed {
+    let embed = embed.truncated();
+    let mut e = CreateEmbed::default();
+    if let Some(title) = &embed.title {
+        e.title(escape(title));
+    }
+    if let Some(description) = &embed.description {
+        e.description(escape(description));
+    }
+    if let Some(url) = &embed.url {
+        // A link can't mention anyone, and escaping it would break ones like `/@user`
+        e.url(url);
+    }
+    if let Some(Ok(timestamp)) = embed.timestamp.as_deref().map(Timestamp::parse) {
+        e.timestamp(timestamp);
+    }
+    if let Some(color) = embed.color {
+        e.color(color);
+    }
+    if let Some(footer) = &embed.footer {
+        e.footer(|f| {
+            f.text(escape(&footer.text));
+            if let Some(icon_url) = &footer.icon_url {
+                f.icon_url(icon_url);
+            }
+            f
+        });
+    }
+    if let Some(image) = &embed.image {
+        e.image(&image.url);
+    }
+    if let Some(thumbnail) = &embed.thumbnail {
+        e.thumbnail(&thumbnail.url);
+    }
+    // Discord ignores these for messages sent by bots, but pass them on like a webhook would
+    if let Some(video) = &embed.video {
+        e.0.insert("video", json!({ "url": video.url }));
+    }
+    if let Some(provider) = &embed.provider {
+        e.0.insert("provider", json!({ "name": provider.name, "url": provider.url }));
+    }
+    if let Some(author) = &embed.author {
+        e.author(|a| {
+            a.name(escape(&author.name));
+            if let Some(icon_url) = &author.icon_url {
+                a.icon_url(icon_url);
+            }
+            if let Some(url) = &author.url {
+                a.url(url);
+            }
+            a
+        });
+    }
+    if let Some(fields) = &embed.fields {
+        e.fields(fields.iter().map(|field| {
+            (
+                escape(&field.name),
+                escape(&field.value),
+                field.inline.unwrap_or(false),
+            )
+        }));
+    }
+    e
+}
+
+/// Find the thread for an application id, creating it if one doesn't exist
+async fn get_app_thread(
+    ctx: &Context,
+    guild: GuildId,
+    dest: &Destination,
+    user: &User,
+) -> Option<ChannelId> {
+    let thread_name = format!("{} - {}", escape(&dest.username), escape(&user.name));
+    // Check to see if a thread already exists for this application id
+    if let Ok(threadsdata) = &ctx.http.get_guild_active_threads(guild.0).await {
+        let threads = &threadsdata.threads;
+        for thread in threads {
+            if let Ok(messages) = &mut ctx.http.get_messages(thread.id.0, "").await {
+                // Reverse the messages because they are listed from last to first
+                messages.reverse();
+                let mut messages = messages.iter();
+                // Skip the first one
+                messages.next().unwrap();
+                if let Some(id_message) = messages.next() {
+                    if id_message.content == format!("{:#}", &dest.app_id) {
+                        if thread.name() != thread_name {
+                            continue;
+                        }
+                        return Some(thread.id);
                     }
                 }
             }
         }
     }
+    // Create a thread for the applicaiton id if one doesn't exist
+    if let Ok(channels) = &guild.channels(&ctx.http).await {
+        if let Some(channel) = channels.get(&ChannelId(dest.channel_id)) {
+            let start_message = channel
+                .send_message(&ctx.http, |m| m.content(&thread_name))
+                .await
+                .expect("Failed to create ID Message");
+            let thread = channel
+                .create_public_thread(&ctx.http, start_message.id, |thread| {
+                    thread.name(&thread_name)
+                })
+                .await
+                .expect("Failed to create public thread");
+            thread
+                .send_message(&ctx.http, |m| m.content(format!("{:#}", &dest.app_id)))
+                .await
+                .expect("Failed to create ID Message");
+            return Some(thread.id);
+        }
+    }
+    None
+}
+
+/// Only let a payload ping who it asks to, and never everyone
+fn create_allowed_mentions<'a>(
+    allowed_mentions: &Option<AllowedMentions>,
+    m: &'a mut CreateAllowedMentions,
+) -> &'a mut CreateAllowedMentions {
+    m.empty_parse();
+    if let Some(allowed) = allowed_mentions {
+        for parse in &allowed.parse {
+            match parse.as_str() {
+                "users" => m.parse(ParseValue::Users),
+                "roles" => m.parse(ParseValue::Roles),
+                _ => m,
+            };
+        }
+        m.users(allowed.users.iter().filter_map(|id| id.parse::<u64>().ok()))
+            .roles(allowed.roles.iter().filter_map(|id| id.parse::<u64>().ok()))
+            .replied_user(allowed.replied_user);
+    }
+    m
+}
+
+fn create_message<'a, 'b>(
+    message: &'a MessageData,
+    m: &'b mut CreateMessage<'a>,
+) -> &'b mut CreateMessage<'a> {
+    if !message.content.is_empty() {
+        m.content(&message.content);
+    }
+    m.tts(message.tts)
+        .set_embeds(create_embeds(&message.embeds))
+        .allowed_mentions(|a| create_allowed_mentions(&message.allowed_mentions, a))
+        .add_files(message.attachments.iter().map(create_attachment))
+}
+
+fn edit_message<'a, 'b>(
+    message: &'a MessageData,
+    m: &'b mut EditMessage<'a>,
+) -> &'b mut EditMessage<'a> {
+    m.content(&message.content)
+        .set_embeds(create_embeds(&message.embeds))
+        .allowed_mentions(|a| create_allowed_mentions(&message.allowed_mentions, a));
+    for attachment in &message.attachments {
+        m.attachment(create_attachment(attachment));
+    }
+    m
+}
+
+/// Re-upload a file under its original name so `attachment://` references in embeds still work
+fn create_attachment(attachment: &Attachment) -> AttachmentType<'_> {
+    AttachmentType::Bytes {
+        data: Cow::Borrowed(&attachment.data),
+        filename: attachment.filename.clone(),
+    }
+}
+
+/// Post a message in the apps thread, or edit the earlier message sent with the same key
+async fn deliver(
+    ctx: &Context,
+    db: &Database,
+    thread: ChannelId,
+    dest: &Destination,
+    message: &MessageData,
+) -> serenity::Result<Message> {
+    let key = match &dest.message_key {
+        Some(key) => key,
+        None => {
+            return thread
+                .send_message(&ctx.http, |m| create_message(message, m))
+                .await;
+        }
+    };
+    let message_coll = db.collection::<MessageCollection>("message");
+    let filter = doc! {"app_id": dest.app_id as i64, "key": &key.key};
+    let mut edited = None;
+    if let Ok(Some(stored)) = message_coll.find_one(filter.clone(), None).await {
+        if stored.channel_id == thread.0 {
+            edited = thread
+                .edit_message(&ctx.http, MessageId(stored.message_id), |m| {
+                    edit_message(message, m)
+                })
+                .await
+                .ok();
+        }
+    }
+    let posted = match edited {
+        Some(edited) => edited,
+        None => {
+            let posted = thread
+                .send_message(&ctx.http, |m| create_message(message, m))
+                .await?;
+            if !key.done {
+                if let Err(e) = message_coll
+                    .update_one(
+                        filter.clone(),
+                        doc! {"$set": {
+                            "channel_id": thread.0 as i64,
+                            "message_id": posted.id.0 as i64,
+                        }},
+                        UpdateOptions::builder().upsert(true).build(),
+                    )
+                    .await
+                {
+                    eprintln!("Failed to store message id for key {}: {e}", key.key);
+                }
+            }
+            posted
+        }
+    };
+    if key.done {
+        if let Err(e) = message_coll.delete_one(filter, None).await {
+            eprintln!("Failed to remove message id for key {}: {e}", key.key);
+        }
+    }
+    Ok(posted)
+}
+
+/// Remember which app posted a message so only that app can edit or delete it
+async fn record_posted(db: &Database, app_id: u64, message: &Message) {
+    let posted_coll = db.collection::<PostedMessageCollection>("posted_message");
+    if let Err(e) = posted_coll
+        .update_one(
+            doc! {"app_id": app_id as i64, "message_id": message.id.0 as i64},
+            doc! {"$set": {"channel_id": message.channel_id.0 as i64}},
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+    {
+        eprintln!("Failed to store posted message {}: {e}", message.id);
+    }
+}
+
+/// Edit a message an app posted, leaving out anything the edit doesn't mention
+pub(crate) async fn edit_posted(
+    http: &Http,
+    posted: &PostedMessageCollection,
+    edit: &DiscordMessageEdit,
+    attachments: &[Attachment],
+) -> serenity::Result<Message> {
+    ChannelId(posted.channel_id)
+        .edit_message(http, MessageId(posted.message_id), |m| {
+            if let Some(content) = &edit.content {
+                m.content(content);
+            }
+            if let Some(embeds) = &edit.embeds {
+                m.set_embeds(create_embeds(embeds));
+            }
+            m.allowed_mentions(|a| create_allowed_mentions(&edit.allowed_mentions, a));
+            for attachment in attachments {
+                m.attachment(create_attachment(attachment));
+            }
+            m
+        })
+        .await
+}
+
+/// Delete a message an app posted
+pub(crate) async fn delete_posted(
+    http: &Http,
+    posted: &PostedMessageCollection,
+) -> serenity::Result<()> {
+    ChannelId(posted.channel_id)
+        .delete_message(http, MessageId(posted.message_id))
+        .await
 }
 
 /// Request an app_id and token to use a webhook
@@ -192,7 +447,7 @@ async fn request(
     } else {
         msg.channel_id.0
     };
-    if let Some(app) = parameters.get(0) {
+    if let Some(app) = parameters.first() {
         let app_id: u32 = rand::random();
         insert_new_app(db, user, app_id, app, guild_id.0, channel).await;
         user.direct_message(&ctx.http, |m| {
@@ -241,7 +496,7 @@ async fn approve(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Mess
             .expect("Failed to send message");
         return;
     }
-    if let Some(app_id) = parameters.get(0) {
+    if let Some(app_id) = parameters.first() {
         let id: u32 = if let Ok(id) = app_id.parse() {
             id
         } else {
@@ -259,40 +514,31 @@ async fn approve(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Mess
             .broadcast_typing(msg.channel_id.0)
             .await
             .expect("Failed to start typing");
-        let token = Yyid::new();
-        let hashed_token = hash(token.as_bytes(), DEFAULT_COST).expect("FAILED TO HASH TOKEN");
+        let (new_token, app_token) = token::generate("default", None);
         ctx.http
             .broadcast_typing(msg.channel_id.0)
             .await
             .expect("Failed to start typing");
-        if verify(token.as_bytes(), &hashed_token).is_err() {
-            panic!("Somehow hashed token was not verified for token");
-        }
         let app_coll = db.collection::<AppCollection>("application");
-        let token = token.to_string();
         let app_id_long = app_id.parse::<u32>().unwrap();
+        let tokens = to_bson(&vec![app_token]).expect("Failed to serialize token");
         app_coll
             .update_one(
                 doc! {"app_id": app_id_long},
-                doc! {"$set":{"token": hashed_token, "approved": Bson::Boolean(true)}},
+                doc! {"$set":{"tokens": tokens, "approved": Bson::Boolean(true)}},
                 None,
             )
             .await
             .expect("Failed to update app");
-        let address = std::env::var("HOOK_ADDRESS").unwrap_or_else(|_| "http://0.0.0.0".into());
-        if let Ok(end_user) = &ctx.http.get_user(user.id).await {
-            end_user
-                .direct_message(&ctx.http, |m| {
-                    m.content(format!(
-                        "The address for your apps webhook is \
-                         {address}/{app_id}/discord?token={token}"
-                    ))
-                })
-                .await
-                .expect("Failed to DM user");
-        } else {
-            panic!("Failed to get owner for app {}", app_id);
-        }
+        send_token(
+            ctx,
+            &user,
+            &app,
+            "default",
+            &new_token,
+            "Senders that can set headers should use `Authorization: Bearer` instead",
+        )
+        .await;
         msg.channel_id
             .say(&ctx.http, "Approval Complete")
             .await
@@ -314,7 +560,7 @@ async fn revoke(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Messa
             .expect("Failed to send message");
         return;
     }
-    if let Some(app_id) = parameters.get(0) {
+    if let Some(app_id) = parameters.first() {
         let app_coll = db.collection::<AppCollection>("application");
         let app_id: u32 = if let Ok(id) = app_id.parse() {
             id
@@ -337,6 +583,7 @@ async fn revoke(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Messa
                     .await
                     .is_ok()
                 {
+                    token::forget(app.app_id);
                     let mut destination = msg.channel_id;
                     if let Ok(id) = std::env::var("APPROVAL_CHANNEL_ID") {
                         if let Ok(channel) = &ctx.http.get_channel(id.parse().unwrap()).await {
@@ -399,6 +646,691 @@ async fn revoke(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Messa
     }
 }
 
+/// Replace one of an app's tokens, keeping the old one valid for a grace period
+async fn rotate(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.is_empty() || parameters.len() > 3 {
+        msg.channel_id
+            .say(
+                &ctx.http,
+                "Please provide an app id and optionally a grace period like 1h and a token name",
+            )
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let grace = match parameters.get(1) {
+        Some(grace) => match heartbeat::parse_interval(grace) {
+            Some(grace) => grace,
+            None => {
+                msg.channel_id
+                    .say(&ctx.http, "Please use a grace period like 30m, 1h or 2d")
+                    .await
+                    .expect("Failed to send message");
+                return;
+            }
+        },
+        None => DEFAULT_ROTATE_GRACE,
+    };
+    let name = parameters.get(2).copied().unwrap_or("default");
+    let (user, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    if !app.tokens.iter().any(|t| t.name == name && !t.is_expired()) {
+        msg.channel_id
+            .say(&ctx.http, format!("{} has no token named {name}", app.app_name))
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let expires = after(grace);
+    ctx.http
+        .broadcast_typing(msg.channel_id.0)
+        .await
+        .expect("Failed to start typing");
+    let (new_token, app_token) = token::generate(name, None);
+    // The old token is kept alongside the new one until the grace period is over
+    let mut tokens = app.tokens.clone();
+    for old in tokens.iter_mut().filter(|t| t.name == name) {
+        old.expires = Some(old.expires.map_or(expires, |old| old.min(expires)));
+    }
+    tokens.push(app_token);
+    save_tokens(db, &app, tokens).await;
+    send_token(
+        ctx,
+        &user,
+        &app,
+        name,
+        &new_token,
+        &format!("The old one keeps working until <t:{}:f>", expires.timestamp_millis() / 1000),
+    )
+    .await;
+    msg.channel_id
+        .say(
+            &ctx.http,
+            format!(
+                "A new {name} token has been sent to the owner, the old one expires in {}",
+                heartbeat::format_interval(grace)
+            ),
+        )
+        .await
+        .expect("Failed to send message");
+}
+
+/// Add or revoke one of an app's named tokens
+async fn tokens(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.len() < 3 || parameters.len() > 4 {
+        msg.channel_id
+            .say(
+                &ctx.http,
+                "Please provide an app id, add or revoke, a token name and optionally an expiry",
+            )
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let (user, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let name = parameters[2];
+    let exists = app.tokens.iter().any(|t| t.name == name && !t.is_expired());
+    let reply = match parameters[1] {
+        "add" if exists => format!("{} already has a token named {name}", app.app_name),
+        "add" => {
+            let expires = match parameters.get(3) {
+                Some(expires) => match heartbeat::parse_interval(expires) {
+                    Some(expires) => Some(after(expires)),
+                    None => {
+                        msg.channel_id
+                            .say(&ctx.http, "Please use an expiry like 12h, 30d or 365d")
+                            .await
+                            .expect("Failed to send message");
+                        return;
+                    }
+                },
+                None => None,
+            };
+            let (new_token, app_token) = token::generate(name, expires);
+            let mut tokens = app.tokens.clone();
+            tokens.push(app_token);
+            save_tokens(db, &app, tokens).await;
+            let note = match expires {
+                Some(expires) => format!("It expires <t:{}:f>", expires.timestamp_millis() / 1000),
+                None => "It doesn't expire".into(),
+            };
+            send_token(ctx, &user, &app, name, &new_token, &note).await;
+            format!("A new {name} token has been sent to the owner")
+        }
+        "revoke" if !exists => format!("{} has no token named {name}", app.app_name),
+        "revoke" => {
+            let tokens = app.tokens.iter().filter(|t| t.name != name).cloned().collect();
+            save_tokens(db, &app, tokens).await;
+            format!("The {name} token for {} has been revoked", app.app_name)
+        }
+        _ => "Please use add or revoke".into(),
+    };
+    msg.channel_id
+        .say(&ctx.http, reply)
+        .await
+        .expect("Failed to send message");
+}
+
+/// Show an app's settings and tokens, including when each token was last used
+async fn info(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.len() != 1 {
+        msg.channel_id
+            .say(&ctx.http, "Please provide only an app id")
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let (user, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let authentication = match &app.signature {
+        Some(scheme) => format!("signed in `{}`", scheme.header),
+        None if app.header_token_only => "token in a header".into(),
+        None => "token in a header or the ?token= query".into(),
+    };
+    let heartbeat = match app.heartbeat_interval {
+        Some(interval) => format!("every {}", heartbeat::format_interval(interval)),
+        None => "off".into(),
+    };
+    let mut lines = vec![
+        format!("**{}** ({})", app.app_name, app.app_id),
+        format!("Owner: {}", escape(&user.username)),
+        format!("Channel: <#{}>", app.channel_id),
+        format!("Authentication: {authentication}"),
+        format!("Heartbeat: {heartbeat}"),
+        "Tokens:".into(),
+    ];
+    let timestamp = |time: DateTime| format!("<t:{}:R>", time.timestamp_millis() / 1000);
+    for app_token in app.tokens.iter().filter(|t| !t.is_expired()) {
+        let mut line = format!(
+            "`{}` created {}, last used {}",
+            app_token.name,
+            timestamp(app_token.created),
+            app_token.last_used.map(timestamp).unwrap_or_else(|| "never".into())
+        );
+        if let Some(expires) = app_token.expires {
+            line.push_str(&format!(", expires {}", timestamp(expires)));
+        }
+        lines.push(line);
+    }
+    msg.channel_id
+        .say(&ctx.http, lines.join("\n"))
+        .await
+        .expect("Failed to send message");
+}
+
+/// The time `secs` seconds from now
+fn after(secs: u64) -> DateTime {
+    let millis = (secs as i64).saturating_mul(1000);
+    DateTime::from_millis(DateTime::now().timestamp_millis().saturating_add(millis))
+}
+
+/// Store an app's tokens, dropping any that have expired
+async fn save_tokens(db: &Database, app: &AppCollection, tokens: Vec<AppToken>) {
+    let tokens: Vec<AppToken> = tokens.into_iter().filter(|t| !t.is_expired()).collect();
+    db.collection::<AppCollection>("application")
+        .update_one(
+            doc! {"app_id": app.app_id as i64},
+            doc! {"$set": {"tokens": to_bson(&tokens).expect("Failed to serialize tokens")}},
+            None,
+        )
+        .await
+        .expect("Failed to update app");
+    token::forget(app.app_id);
+}
+
+/// DM the owner of an app a new token along with the address to use it with
+async fn send_token(
+    ctx: &Context,
+    user: &UserCollection,
+    app: &AppCollection,
+    name: &str,
+    new_token: &str,
+    note: &str,
+) {
+    let address = std::env::var("HOOK_ADDRESS").unwrap_or_else(|_| "http://0.0.0.0".into());
+    let app_id = app.app_id;
+    if let Ok(end_user) = &ctx.http.get_user(user.id).await {
+        end_user
+            .direct_message(&ctx.http, |m| {
+                m.content(format!(
+                    "The {name} token for {} is `{new_token}`, its address is \
+                     {address}/{app_id}/discord?token={new_token}\n{note}",
+                    app.app_name
+                ))
+            })
+            .await
+            .expect("Failed to DM user");
+    } else {
+        panic!("Failed to get owner for app {}", app_id);
+    }
+}
+
+/// Generate a signing secret for an app and DM it to the owner
+///
+/// Services like Sentry that generate their own secret can pass it as a second parameter
+/// instead, the command message is deleted so it doesn't stay in the channel
+async fn secret(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.is_empty() || parameters.len() > 2 {
+        msg.channel_id
+            .say(&ctx.http, "Please provide an app id and optionally an existing secret")
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let provided = parameters.get(1).map(|secret| secret.to_string());
+    if provided.is_some() {
+        if let Err(e) = msg.delete(&ctx.http).await {
+            eprintln!("Failed to delete secret command: {e}");
+        }
+    }
+    let (user, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let app_id = app.app_id as i64;
+    let secret = provided.clone().unwrap_or_else(|| Yyid::new().to_string());
+    let app_coll = db.collection::<AppCollection>("application");
+    app_coll
+        .update_one(
+            doc! {"app_id": app_id},
+            doc! {"$set":{"secret": &secret}},
+            None,
+        )
+        .await
+        .expect("Failed to update app");
+    if provided.is_some() {
+        msg.channel_id
+            .say(&ctx.http, format!("The secret for {} has been saved", app.app_name))
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let address = std::env::var("HOOK_ADDRESS").unwrap_or_else(|_| "http://0.0.0.0".into());
+    if let Ok(end_user) = &ctx.http.get_user(user.id).await {
+        end_user
+            .direct_message(&ctx.http, |m| {
+                m.content(format!(
+                    "The signing secret for {} is `{secret}`\n\
+                     Signed webhooks can be sent to {address}/{app_id}/github \
+                     or {address}/{app_id}/gitea, GitLab can use it as the secret token \
+                     for {address}/{app_id}/gitlab",
+                    app.app_name
+                ))
+            })
+            .await
+            .expect("Failed to DM user");
+    } else {
+        panic!("Failed to get owner for app {}", app_id);
+    }
+    msg.channel_id
+        .say(&ctx.http, "A new secret has been sent to the owner")
+        .await
+        .expect("Failed to send message");
+}
+
+/// Show, set or remove the scheme an app signs its requests with
+///
+/// The scheme is either the name of a preset or JSON after `set`, like a template
+async fn signature(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "Please provide an app id")
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let (_, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let scheme = match parameters.get(1).copied() {
+        None => {
+            let reply = match &app.signature {
+                Some(scheme) => format!(
+                    "```json\n{}\n```",
+                    serde_json::to_string_pretty(scheme).expect("Failed to serialize scheme")
+                ),
+                None => format!("{} is authenticated with its token", app.app_name),
+            };
+            msg.channel_id
+                .say(&ctx.http, reply)
+                .await
+                .expect("Failed to send message");
+            return;
+        }
+        Some("off") => None,
+        Some("set") => match serde_json::from_str(trailing_text(&msg.content, 3)) {
+            Ok(scheme) => Some(scheme),
+            Err(e) => {
+                msg.channel_id
+                    .say(&ctx.http, format!("That scheme is not valid: {e}"))
+                    .await
+                    .expect("Failed to send message");
+                return;
+            }
+        },
+        Some(preset) => match SignatureScheme::preset(preset) {
+            Some(scheme) => Some(scheme),
+            None => {
+                msg.channel_id
+                    .say(&ctx.http, "Please use github, slack, stripe, set <json> or off")
+                    .await
+                    .expect("Failed to send message");
+                return;
+            }
+        },
+    };
+    let saved = match &scheme {
+        Some(scheme) => to_bson(scheme).expect("Failed to serialize scheme"),
+        None => Bson::Null,
+    };
+    db.collection::<AppCollection>("application")
+        .update_one(
+            doc! {"app_id": app.app_id as i64},
+            doc! {"$set": {"signature": saved}},
+            None,
+        )
+        .await
+        .expect("Failed to update app");
+    let reply = match (scheme, &app.secret) {
+        (None, _) => format!("{} is authenticated with its token again", app.app_name),
+        (Some(_), Some(_)) => format!("{} now has to sign its requests", app.app_name),
+        (Some(_), None) => format!(
+            "{} now has to sign its requests, create or save its secret with the secret command",
+            app.app_name
+        ),
+    };
+    msg.channel_id
+        .say(&ctx.http, reply)
+        .await
+        .expect("Failed to send message");
+}
+
+/// Show, set or preview the template an app uses for the `json` route
+///
+/// The JSON for `set` and `preview` is everything after the sub command, optionally wrapped
+/// in a code block
+async fn template(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "Please provide an app id")
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let (_, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let json = trailing_text(&msg.content, 3);
+    match parameters.get(1).copied() {
+        None => {
+            let reply = match &app.template {
+                Some(template) => format!(
+                    "```json\n{}\n```",
+                    serde_json::to_string_pretty(template).expect("Failed to serialize template")
+                ),
+                None => format!("{} has no template", app.app_name),
+            };
+            msg.channel_id
+                .say(&ctx.http, reply)
+                .await
+                .expect("Failed to send message");
+        }
+        Some("set") => {
+            let template: EmbedTemplate = match serde_json::from_str(json) {
+                Ok(template) => template,
+                Err(e) => {
+                    msg.channel_id
+                        .say(&ctx.http, format!("That template is not valid: {e}"))
+                        .await
+                        .expect("Failed to send message");
+                    return;
+                }
+            };
+            let template = to_bson(&template).expect("Failed to serialize template");
+            let app_coll = db.collection::<AppCollection>("application");
+            app_coll
+                .update_one(
+                    doc! {"app_id": app.app_id as i64},
+                    doc! {"$set":{"template": template}},
+                    None,
+                )
+                .await
+                .expect("Failed to update app");
+            msg.channel_id
+                .say(&ctx.http, format!("The template for {} has been saved", app.app_name))
+                .await
+                .expect("Failed to send message");
+        }
+        Some("preview") => {
+            let template = if let Some(template) = &app.template {
+                template
+            } else {
+                msg.channel_id
+                    .say(&ctx.http, format!("{} has no template", app.app_name))
+                    .await
+                    .expect("Failed to send message");
+                return;
+            };
+            let message = match serde_json::from_str(json) {
+                Ok(sample) => template.render(&sample),
+                Err(e) => {
+                    msg.channel_id
+                        .say(&ctx.http, format!("That sample is not valid JSON: {e}"))
+                        .await
+                        .expect("Failed to send message");
+                    return;
+                }
+            };
+            let result = if message.is_empty() {
+                msg.channel_id
+                    .say(&ctx.http, "The template rendered an empty message")
+                    .await
+            } else {
+                msg.channel_id
+                    .send_message(&ctx.http, |m| create_message(&message, m))
+                    .await
+            };
+            if let Err(e) = result {
+                msg.channel_id
+                    .say(&ctx.http, format!("Discord rejected the preview: {e}"))
+                    .await
+                    .expect("Failed to send message");
+            }
+        }
+        Some(_) => {
+            msg.channel_id
+                .say(&ctx.http, "Please use `set` or `preview`")
+                .await
+                .expect("Failed to send message");
+        }
+    }
+}
+
+/// List, add or remove the RSS and Atom feeds polled for an app
+async fn feed(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.is_empty() {
+        msg.channel_id
+            .say(&ctx.http, "Please provide an app id")
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let (_, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let app_id = app.app_id as i64;
+    let feed_coll = db.collection::<FeedCollection>("feed");
+    // Discord users wrap links in <> to hide the preview
+    let url = parameters
+        .get(2)
+        .map(|url| url.trim_start_matches('<').trim_end_matches('>'));
+    let reply = match (parameters.get(1).copied(), url) {
+        (None, _) => {
+            let feeds: Vec<FeedCollection> = feed_coll
+                .find(doc! {"app_id": app_id}, None)
+                .await
+                .expect("Failed to find feeds")
+                .try_collect()
+                .await
+                .unwrap_or_default();
+            if feeds.is_empty() {
+                format!("{} has no feeds", app.app_name)
+            } else {
+                feeds
+                    .iter()
+                    .map(|feed| format!("<{}>", feed.url))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
+        }
+        (Some("add"), Some(url)) => {
+            let existing = feed_coll
+                .find_one(doc! {"app_id": app_id, "url": url}, None)
+                .await
+                .expect("Failed to find feed");
+            match (existing, feed::fetch(url).await) {
+                (Some(_), _) => "That feed has already been added".into(),
+                (None, Err(e)) => {
+                    // The error could describe hosts on HookMe's network, so it's only logged
+                    eprintln!("Failed to read feed {url} for app {}: {e}", app.app_id);
+                    "Could not read that feed, it has to be a public RSS, Atom or JSON feed".into()
+                }
+                (None, Ok(fetched)) => {
+                    // Only entries published from now on get posted
+                    feed_coll
+                        .insert_one(
+                            FeedCollection {
+                                _id: ObjectId::new(),
+                                app_id: app.app_id,
+                                url: url.into(),
+                                seen: feed::seen(&fetched, &[]),
+                            },
+                            None,
+                        )
+                        .await
+                        .expect("Failed to insert feed");
+                    let title = fetched.title.map(|title| title.content);
+                    format!("Now following {}", title.as_deref().unwrap_or(url))
+                }
+            }
+        }
+        (Some("remove"), Some(url)) => {
+            let removed = feed_coll
+                .delete_one(doc! {"app_id": app_id, "url": url}, None)
+                .await
+                .expect("Failed to remove feed");
+            if removed.deleted_count > 0 {
+                "That feed has been removed".into()
+            } else {
+                "That feed was not found".into()
+            }
+        }
+        _ => "Please use `add <url>` or `remove <url>`".into(),
+    };
+    msg.channel_id
+        .say(&ctx.http, reply)
+        .await
+        .expect("Failed to send message");
+}
+
+/// Show, set or turn off how often an app is expected to send something
+async fn heartbeat(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.is_empty() || parameters.len() > 2 {
+        msg.channel_id
+            .say(&ctx.http, "Please provide an app id and optionally an interval like 26h")
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let (_, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let app_coll = db.collection::<AppCollection>("application");
+    let filter = doc! {"app_id": app.app_id as i64};
+    let reply = match parameters.get(1).copied() {
+        None => match app.heartbeat_interval {
+            Some(interval) => format!(
+                "{} is expected every {}",
+                app.app_name,
+                heartbeat::format_interval(interval)
+            ),
+            None => format!("{} has no heartbeat", app.app_name),
+        },
+        Some("off") => {
+            app_coll
+                .update_one(
+                    filter,
+                    doc! {"$set": {"heartbeat_interval": Bson::Null, "heartbeat_missed": false}},
+                    None,
+                )
+                .await
+                .expect("Failed to update app");
+            format!("{} is no longer watched", app.app_name)
+        }
+        Some(interval) => match heartbeat::parse_interval(interval) {
+            Some(interval) => {
+                // Start the clock now rather than from whenever the app last sent something
+                app_coll
+                    .update_one(
+                        filter,
+                        doc! {"$set": {
+                            "heartbeat_interval": interval as i64,
+                            "last_seen": DateTime::now(),
+                            "heartbeat_missed": false,
+                        }},
+                        None,
+                    )
+                    .await
+                    .expect("Failed to update app");
+                format!(
+                    "{} is now expected every {}",
+                    app.app_name,
+                    heartbeat::format_interval(interval)
+                )
+            }
+            None => "Please use an interval like 30m, 26h or 1d12h, or off".into(),
+        },
+    };
+    msg.channel_id
+        .say(&ctx.http, reply)
+        .await
+        .expect("Failed to send message");
+}
+
+async fn header_only(db: &Database, parameters: Vec<&str>, ctx: &Context, msg: &Message) {
+    if parameters.is_empty() || parameters.len() > 2 {
+        msg.channel_id
+            .say(&ctx.http, "Please provide an app id and optionally on or off")
+            .await
+            .expect("Failed to send message");
+        return;
+    }
+    let (_, app) = if let Some(found) = get_owned_app(db, parameters[0], ctx, msg).await {
+        found
+    } else {
+        return;
+    };
+    let header_only = match parameters.get(1).copied() {
+        None => {
+            let reply = if app.header_token_only {
+                format!("{} only accepts the token in a header", app.app_name)
+            } else {
+                format!("{} accepts the token in a header or the ?token= query", app.app_name)
+            };
+            msg.channel_id
+                .say(&ctx.http, reply)
+                .await
+                .expect("Failed to send message");
+            return;
+        }
+        Some("on") => true,
+        Some("off") => false,
+        Some(_) => {
+            msg.channel_id
+                .say(&ctx.http, "Please use on or off")
+                .await
+                .expect("Failed to send message");
+            return;
+        }
+    };
+    db.collection::<AppCollection>("application")
+        .update_one(
+            doc! {"app_id": app.app_id as i64},
+            doc! {"$set": {"header_token_only": header_only}},
+            None,
+        )
+        .await
+        .expect("Failed to update app");
+    let reply = if header_only {
+        format!("{} now ignores the ?token= query", app.app_name)
+    } else {
+        format!("{} accepts the ?token= query again", app.app_name)
+    };
+    msg.channel_id
+        .say(&ctx.http, reply)
+        .await
+        .expect("Failed to send message");
+}
+
 async fn help(prefix: &char, ctx: &Context, msg: &Message) {
     let user = &msg.author;
     let bot_user = &ctx
@@ -411,7 +1343,7 @@ async fn help(prefix: &char, ctx: &Context, msg: &Message) {
             e.title("Hook Me Commands:")
                 .author(|a| {
                     a.name(&bot_user.name)
-                        .icon_url(&bot_user.avatar_url().unwrap())
+                        .icon_url(bot_user.avatar_url().unwrap())
                 })
                 .fields(vec![
                     (
@@ -429,6 +1361,51 @@ async fn help(prefix: &char, ctx: &Context, msg: &Message) {
                         "Revoke or Decline access",
                         false,
                     ),
+                    (
+                        format!("{prefix}info <app id>"),
+                        "Show an app's settings and when each of its tokens was last used",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}token <app id> <add|revoke> <name> [expiry]"),
+                        "Add a named token for another sender, or revoke one",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}rotate <app id> [grace period] [name]"),
+                        "Send the owner a new token, the old one works for the grace period",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}secret <app id> [secret]"),
+                        "Generate a new secret for signed webhooks, or save one like Sentry's",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}feed <app id> [add|remove] [url]"),
+                        "List, add or remove the feeds posted to an app's thread",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}heartbeat <app id> [interval|off]"),
+                        "Warn the owner when an app doesn't send anything for the interval",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}headeronly <app id> [on|off]"),
+                        "Only accept the app token from headers, never the ?token= query",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}signature <app id> [github|slack|stripe|set|off] [json]"),
+                        "Require requests to be signed with the app's secret instead of a token",
+                        false,
+                    ),
+                    (
+                        format!("{prefix}template <app id> [set|preview] [json]"),
+                        "Show, set or preview the template for the json route",
+                        false,
+                    ),
                 ])
         })
     })
@@ -456,6 +1433,61 @@ async fn has_permission(key: &str, ctx: &Context, msg: &Message, user: &User, gu
     true
 }
 
+/// Find an approved app the author owns or can manage as an admin, telling them if they can't
+async fn get_owned_app(
+    db: &Database,
+    app_id: &str,
+    ctx: &Context,
+    msg: &Message,
+) -> Option<(UserCollection, AppCollection)> {
+    let found = match app_id.parse() {
+        Ok(app_id) => get_app_and_user(db, app_id).await,
+        Err(_) => None,
+    };
+    let (user, app) = if let Some(found) = found {
+        found
+    } else {
+        msg.channel_id
+            .say(&ctx.http, "There was an error in that request")
+            .await
+            .expect("Failed to send message");
+        return None;
+    };
+    let guild_id = &msg.guild_id.expect("Failed to get guild id");
+    if user.id != msg.author.id.0
+        && !has_permission("ADMIN_ROLE_ID", ctx, msg, &msg.author, guild_id.0).await
+    {
+        return None;
+    }
+    if !app.approved.as_bool().unwrap_or(false) {
+        msg.channel_id
+            .say(&ctx.http, "That app has not been approved yet")
+            .await
+            .expect("Failed to send message");
+        return None;
+    }
+    Some((user, app))
+}
+
+/// The text after the first `words` words of a message, without a surrounding code block
+fn trailing_text(content: &str, words: usize) -> &str {
+    let mut rest = content.trim_start();
+    for _ in 0..words {
+        rest = match rest.find(char::is_whitespace) {
+            Some(end) => rest[end..].trim_start(),
+            None => "",
+        };
+    }
+    let rest = rest.trim();
+    let rest = rest
+        .strip_prefix("```json")
+        .or_else(|| rest.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .or_else(|| rest.strip_prefix('`').and_then(|rest| rest.strip_suffix('`')))
+        .unwrap_or(rest);
+    rest.trim()
+}
+
 async fn get_app_and_user(db: &Database, app_id: u32) -> Option<(UserCollection, AppCollection)> {
     let app_coll = db.collection::<AppCollection>("application");
     let user_coll = db.collection::<UserCollection>("user");
@@ -522,7 +1554,7 @@ async fn insert_new_app(
         _id: ObjectId::new(),
         app_id: app_id as u64,
         app_name: app_name.into(),
-        token: "".into(),
+        tokens: vec![],
         owner: crate::UserRef {
             reference: "user".into(),
             id,
@@ -530,6 +1562,13 @@ async fn insert_new_app(
         server_id: guild_id,
         channel_id,
         approved: Bson::Boolean(false),
+        secret: None,
+        template: None,
+        heartbeat_interval: None,
+        last_seen: None,
+        heartbeat_missed: false,
+        header_token_only: false,
+        signature: None,
     };
     let app_coll = db.collection::<AppCollection>("application");
     app_coll
@@ -0,0 +1,163 @@
+use crate::body_type::{non_empty, Embed, EmbedAuthor, EmbedData, EmbedField, MessageKey};
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+
+const GREEN: u32 = 0x2ea44f;
+const RED: u32 = 0xd73a49;
+const YELLOW: u32 = 0xdbab09;
+const GREY: u32 = 0x6a737d;
+
+#[derive(Deserialize, Debug)]
+pub struct Repo {
+    #[serde(alias = "full_name")]
+    slug: String,
+    #[serde(default, alias = "forge_url")]
+    link: String,
+}
+
+/// Drone calls these stages and Woodpecker calls them workflows
+#[derive(Deserialize, Debug)]
+pub struct Stage {
+    name: String,
+    #[serde(alias = "state")]
+    status: String,
+}
+
+/// A Drone build or a Woodpecker pipeline, which is the same thing with a few renamed fields
+#[derive(Deserialize, Debug)]
+pub struct Build {
+    number: u64,
+    status: String,
+    #[serde(default)]
+    event: String,
+    #[serde(default, alias = "commit")]
+    after: String,
+    #[serde(default, alias = "branch")]
+    target: String,
+    #[serde(default)]
+    message: String,
+    #[serde(default, alias = "author")]
+    author_login: String,
+    #[serde(default)]
+    author_avatar: String,
+    #[serde(default)]
+    started: i64,
+    #[serde(default)]
+    finished: i64,
+    #[serde(default, alias = "workflows")]
+    stages: Vec<Stage>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct System {
+    #[serde(default)]
+    link: String,
+}
+
+/// The build webhook sent by Drone and Woodpecker when a build is created or updated
+#[derive(Deserialize, Debug)]
+pub struct DroneWebhook {
+    repo: Repo,
+    #[serde(alias = "pipeline")]
+    build: Build,
+    #[serde(default)]
+    system: System,
+}
+
+impl DroneWebhook {
+    /// Whether the build won't change again
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.build.status.as_str(),
+            "success" | "failure" | "error" | "killed" | "skipped" | "declined"
+        )
+    }
+
+    fn url(&self) -> String {
+        if self.system.link.is_empty() {
+            self.repo.link.clone()
+        } else {
+            format!("{}/{}/{}", self.system.link, self.repo.slug, self.build.number)
+        }
+    }
+
+    fn render(&self) -> EmbedData {
+        let build = &self.build;
+        let color = match build.status.as_str() {
+            "success" => GREEN,
+            "failure" | "error" => RED,
+            "killed" | "skipped" | "declined" => GREY,
+            _ => YELLOW,
+        };
+        let mut description = build.message.lines().next().unwrap_or("").to_string();
+        if !build.after.is_empty() {
+            description.push_str(&format!(
+                "\n`{}` on `{}`",
+                build.after.chars().take(8).collect::<String>(),
+                build.target
+            ));
+        }
+        if self.is_finished() && build.started > 0 && build.finished >= build.started {
+            description.push_str(&format!(" in {}s", build.finished - build.started));
+        }
+        let fields = if build.stages.is_empty() {
+            vec![]
+        } else {
+            let stages = build
+                .stages
+                .iter()
+                .map(|stage| format!("{}: {}", stage.name, stage.status))
+                .collect::<Vec<String>>()
+                .join("\n");
+            vec![EmbedField::new("Stages", &stages, false)]
+        };
+        let mut title = format!("[{}] Build #{} {}", self.repo.slug, build.number, build.status);
+        if let Some(event) = non_empty(&build.event) {
+            title.push_str(&format!(" ({event})"));
+        }
+        EmbedData::new(
+            &title,
+            &description,
+            &self.url(),
+            color,
+            "Drone",
+            EmbedAuthor::new(&build.author_login, "", &build.author_avatar),
+            fields,
+        )
+    }
+}
+
+impl Embed for DroneWebhook {
+    fn get_username(&self) -> String { self.build.author_login.clone() }
+
+    fn get_avatar_url(&self) -> String { self.build.author_avatar.clone() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { vec![self.render()] }
+
+    fn get_message_key(&self) -> Option<MessageKey> {
+        let key = format!("{}#{}", self.repo.slug, self.build.number);
+        Some(MessageKey::new(&key, self.is_finished()))
+    }
+}
+
+/// Drone and Woodpecker build webhooks, authenticated with the app token
+pub struct Drone;
+
+impl Source for Drone {
+    fn name(&self) -> &'static str { "drone" }
+
+    fn detect(&self, request: &Incoming) -> bool {
+        request.header("X-Drone-Event").is_some()
+            || serde_json::from_slice::<DroneWebhook>(request.body).is_ok()
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        // Drone also sends user and repo events to the same endpoint
+        if !matches!(request.header("X-Drone-Event"), None | Some("build")) {
+            return Ok(None);
+        }
+        let webhook: DroneWebhook = serde_json::from_slice(request.body)?;
+        Ok(Some(Box::new(webhook)))
+    }
+}
@@ -0,0 +1,289 @@
+use crate::body_type::{non_empty, summary, Destination, EmbedAuthor, EmbedData, MessageData};
+use crate::body_type::{chunk_embeds, EmbedMedia};
+use crate::{get_approved_app, send_message, FeedCollection, SendEmbed};
+use feed_rs::model::{Entry, Feed};
+use futures_util::TryStreamExt;
+use mongodb::bson::doc;
+use mongodb::Database;
+use reqwest::header::LOCATION;
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+type FeedError = Box<dyn Error + Send + Sync>;
+
+const ORANGE: u32 = 0xf26522;
+
+/// How often feeds are checked when `FEED_INTERVAL` isn't set, in seconds
+const DEFAULT_INTERVAL: u64 = 15 * 60;
+
+/// How many entry ids are remembered for each feed, well above what feeds usually list
+const MAX_SEEN: usize = 500;
+
+/// How many redirects are followed when fetching a feed
+const MAX_REDIRECTS: usize = 5;
+
+/// Download and parse a RSS, Atom or JSON feed
+///
+/// Feeds are only fetched from public addresses so they can't be used to reach the services
+/// running next to HookMe, redirects are followed by hand so every hop is checked
+pub async fn fetch(url: &str) -> Result<Feed, FeedError> {
+    let mut url = Url::parse(url)?;
+    for _ in 0..=MAX_REDIRECTS {
+        let response = client(&url).await?.get(url.clone()).send().await?;
+        if response.status().is_redirection() {
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .ok_or("redirect without a location")?;
+            url = url.join(location)?;
+            continue;
+        }
+        let body = response.error_for_status()?.bytes().await?;
+        return Ok(feed_rs::parser::parse(body.as_ref())?);
+    }
+    Err("too many redirects".into())
+}
+
+/// A client that only connects to the public address the URL's host resolves to
+///
+/// The address is pinned so the host can't resolve somewhere else once it's been checked, and
+/// a user agent is set since some servers refuse requests without one
+async fn client(url: &Url) -> Result<reqwest::Client, FeedError> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("only http and https feeds can be fetched".into());
+    }
+    let host = url.host_str().ok_or("the URL has no host")?;
+    let port = url.port_or_known_default().ok_or("the URL has no port")?;
+    let ip = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs: Vec<SocketAddr> = match ip.parse::<IpAddr>() {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => tokio::net::lookup_host((host, port)).await?.collect(),
+    };
+    let addr = match addrs.first() {
+        Some(addr) if addrs.iter().all(|addr| is_public(addr.ip())) => *addr,
+        _ => return Err(format!("{host} is not a public address").into()),
+    };
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!("HookMe/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(30))
+        .redirect(Policy::none())
+        .resolve(host, addr)
+        .build()?)
+}
+
+/// Whether an address is reachable on the internet rather than a local or private network
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                || a == 0)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    // Unique local fc00::/7 and link local fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Check every registered feed on an interval and post new entries in the apps thread
+pub async fn poll(state: SendEmbed, db: Database) {
+    let interval = std::env::var("FEED_INTERVAL")
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL);
+    let mut interval = tokio::time::interval(Duration::from_secs(interval));
+    loop {
+        interval.tick().await;
+        let feeds: Vec<FeedCollection> = match db
+            .collection::<FeedCollection>("feed")
+            .find(None, None)
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+            Err(e) => {
+                eprintln!("Failed to find feeds: {e}");
+                continue;
+            }
+        };
+        for feed in feeds {
+            if let Err(e) = check(&state, &db, &feed).await {
+                eprintln!("Failed to check feed {} for app {}: {e}", feed.url, feed.app_id);
+            }
+        }
+    }
+}
+
+async fn check(state: &SendEmbed, db: &Database, feed: &FeedCollection) -> Result<(), FeedError> {
+    // Revoked apps keep their feeds but nothing is posted for them
+    let (app, user) = match get_approved_app(db, feed.app_id as i64).await {
+        Some(found) => found,
+        None => return Ok(()),
+    };
+    let fetched = fetch(&feed.url).await?;
+    let new: Vec<&Entry> = fetched
+        .entries
+        .iter()
+        .filter(|entry| !feed.seen.contains(&entry.id))
+        .collect();
+    if new.is_empty() {
+        return Ok(());
+    }
+    let embeds: Vec<EmbedData> = new.iter().rev().map(|entry| render(&fetched, entry)).collect();
+    // Entries that don't fit in one message are split over several so none are lost
+    for embeds in chunk_embeds(embeds) {
+        let destination = Destination::new(
+            &app.app_name,
+            "",
+            app.server_id,
+            app.channel_id,
+            user.id,
+            app.app_id,
+        );
+        send_message(state, destination, MessageData::from_embeds(embeds)).await;
+    }
+    db.collection::<FeedCollection>("feed")
+        .update_one(
+            doc! {"_id": feed._id},
+            doc! {"$set": {"seen": seen(&fetched, &feed.seen)}},
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+/// The ids to remember after a fetch, newest first
+pub fn seen(feed: &Feed, previous: &[String]) -> Vec<String> {
+    let mut seen: Vec<String> = feed.entries.iter().map(|entry| entry.id.clone()).collect();
+    seen.extend(previous.iter().filter(|id| !seen.contains(id)).cloned().collect::<Vec<_>>());
+    seen.truncate(MAX_SEEN);
+    seen
+}
+
+fn render(feed: &Feed, entry: &Entry) -> EmbedData {
+    let feed_title = feed
+        .title
+        .as_ref()
+        .map(|title| title.content.clone())
+        .unwrap_or_else(|| "Feed".into());
+    let text = entry
+        .summary
+        .as_ref()
+        .map(|summary| summary.content.clone())
+        .or_else(|| entry.content.as_ref().and_then(|content| content.body.clone()))
+        .unwrap_or_default();
+    let author = entry
+        .authors
+        .first()
+        .map(|author| author.name.clone())
+        .unwrap_or_else(|| feed_title.clone());
+    let mut embed = EmbedData::new(
+        &entry
+            .title
+            .as_ref()
+            .map(|title| strip_html(&title.content))
+            .unwrap_or_else(|| "New entry".into()),
+        &summary(Some(&strip_html(&text))),
+        entry
+            .links
+            .first()
+            .map(|link| link.href.as_str())
+            .unwrap_or_default(),
+        ORANGE,
+        &feed_title,
+        EmbedAuthor::new(
+            &author,
+            feed.links
+                .first()
+                .map(|link| link.href.as_str())
+                .unwrap_or_default(),
+            "",
+        ),
+        vec![],
+    );
+    embed.timestamp = entry
+        .published
+        .or(entry.updated)
+        .map(|date| date.to_rfc3339());
+    embed.thumbnail = feed
+        .icon
+        .as_ref()
+        .or(feed.logo.as_ref())
+        .and_then(|image| non_empty(&image.uri))
+        .map(|url| EmbedMedia::new(&url));
+    embed
+}
+
+/// Feeds often put HTML in summaries, keep just the text
+fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fetch, is_public};
+
+    #[test]
+    fn public_addresses() {
+        for ip in ["1.1.1.1", "93.184.216.34", "2606:4700:4700::1111"] {
+            assert!(is_public(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in [
+            "127.0.0.1",
+            "10.0.0.5",
+            "172.16.3.4",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+        ] {
+            assert!(!is_public(ip.parse().unwrap()), "{ip}");
+        }
+    }
+
+    #[tokio::test]
+    async fn local_feeds_are_refused() {
+        for url in ["http://127.0.0.1:27017/", "http://[::1]/feed", "http://localhost/feed"] {
+            let error = fetch(url).await.err().unwrap().to_string();
+            assert!(error.ends_with("is not a public address"), "{url}: {error}");
+        }
+        assert!(fetch("ftp://example.com/feed").await.is_err());
+    }
+}
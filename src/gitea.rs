@@ -0,0 +1,380 @@
+use crate::body_type::{commit_list, short_ref, summary, Commit, Embed, EmbedAuthor, EmbedData};
+use crate::body_type::EmbedField;
+use crate::signature;
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+
+const GREEN: u32 = 0x609926;
+const RED: u32 = 0xdb2828;
+const PURPLE: u32 = 0xa333c8;
+const BLUE: u32 = 0x2185d0;
+const YELLOW: u32 = 0xfbbd08;
+const GREY: u32 = 0x767676;
+
+#[derive(Deserialize, Debug)]
+pub struct User {
+    login: String,
+    #[serde(default)]
+    avatar_url: String,
+    /// Only sent by newer Gitea and Forgejo versions
+    #[serde(default)]
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Repository {
+    full_name: String,
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    #[serde(default)]
+    compare_url: String,
+    commits: Vec<Commit>,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PullRequest {
+    number: u64,
+    title: String,
+    html_url: String,
+    body: Option<String>,
+    #[serde(default)]
+    merged: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PullRequestEvent {
+    action: String,
+    pull_request: PullRequest,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Issue {
+    number: u64,
+    title: String,
+    html_url: String,
+    body: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssuesEvent {
+    action: String,
+    issue: Issue,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Comment {
+    html_url: String,
+    body: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssueCommentEvent {
+    action: String,
+    issue: Issue,
+    comment: Comment,
+    #[serde(default)]
+    is_pull: bool,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Release {
+    tag_name: String,
+    name: Option<String>,
+    #[serde(default)]
+    html_url: String,
+    body: Option<String>,
+    #[serde(default)]
+    prerelease: bool,
+    #[serde(default)]
+    draft: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReleaseEvent {
+    action: String,
+    release: Release,
+    repository: Repository,
+    sender: User,
+}
+
+/// Sent for both the `create` and `delete` events
+#[derive(Deserialize, Debug)]
+pub struct RefEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    ref_type: String,
+    repository: Repository,
+    sender: User,
+}
+
+/// A package in Gitea's package registry
+#[derive(Deserialize, Debug)]
+pub struct Package {
+    owner: User,
+    /// Only set when the package is linked to a repository
+    repository: Option<Repository>,
+    #[serde(rename = "type")]
+    package_type: String,
+    name: String,
+    version: String,
+    #[serde(default)]
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PackageEvent {
+    action: String,
+    package: Package,
+    sender: User,
+}
+
+/// A Gitea or Forgejo webhook payload, picked using the `X-Gitea-Event` header
+#[derive(Debug)]
+pub enum GiteaEvent {
+    Push(PushEvent),
+    PullRequest(PullRequestEvent),
+    Issues(IssuesEvent),
+    IssueComment(IssueCommentEvent),
+    Release(ReleaseEvent),
+    Create(RefEvent),
+    Delete(RefEvent),
+    Package(PackageEvent),
+}
+
+impl GiteaEvent {
+    /// Parse the body for the given event name, events we don't render give `Ok(None)`
+    pub fn parse(event: &str, body: &[u8]) -> Result<Option<GiteaEvent>, serde_json::Error> {
+        Ok(Some(match event {
+            "push" => GiteaEvent::Push(serde_json::from_slice(body)?),
+            "pull_request" => GiteaEvent::PullRequest(serde_json::from_slice(body)?),
+            "issues" => GiteaEvent::Issues(serde_json::from_slice(body)?),
+            "issue_comment" => GiteaEvent::IssueComment(serde_json::from_slice(body)?),
+            "release" => GiteaEvent::Release(serde_json::from_slice(body)?),
+            "create" => GiteaEvent::Create(serde_json::from_slice(body)?),
+            "delete" => GiteaEvent::Delete(serde_json::from_slice(body)?),
+            "package" => GiteaEvent::Package(serde_json::from_slice(body)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn sender(&self) -> &User {
+        match self {
+            GiteaEvent::Push(e) => &e.sender,
+            GiteaEvent::PullRequest(e) => &e.sender,
+            GiteaEvent::Issues(e) => &e.sender,
+            GiteaEvent::IssueComment(e) => &e.sender,
+            GiteaEvent::Release(e) => &e.sender,
+            GiteaEvent::Create(e) | GiteaEvent::Delete(e) => &e.sender,
+            GiteaEvent::Package(e) => &e.sender,
+        }
+    }
+
+    fn author(&self) -> EmbedAuthor {
+        let sender = self.sender();
+        EmbedAuthor::new(&sender.login, &sender.html_url, &sender.avatar_url)
+    }
+
+    fn render(&self) -> EmbedData {
+        match self {
+            GiteaEvent::Push(e) => render_push(e, self.author()),
+            GiteaEvent::PullRequest(e) => render_pull_request(e, self.author()),
+            GiteaEvent::Issues(e) => render_issue(e, self.author()),
+            GiteaEvent::IssueComment(e) => render_issue_comment(e, self.author()),
+            GiteaEvent::Release(e) => render_release(e, self.author()),
+            GiteaEvent::Create(e) => render_ref(e, "Created", GREEN, self.author()),
+            GiteaEvent::Delete(e) => render_ref(e, "Deleted", RED, self.author()),
+            GiteaEvent::Package(e) => render_package(e, self.author()),
+        }
+    }
+}
+
+impl Embed for GiteaEvent {
+    fn get_username(&self) -> String { self.sender().login.clone() }
+
+    fn get_avatar_url(&self) -> String { self.sender().avatar_url.clone() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { vec![self.render()] }
+}
+
+fn render_push(push: &PushEvent, author: EmbedAuthor) -> EmbedData {
+    let branch = short_ref(&push.git_ref);
+    let repository = &push.repository.full_name;
+    let count = push.commits.len();
+    let title = if count == 1 {
+        format!("[{repository}:{branch}] 1 new commit")
+    } else {
+        format!("[{repository}:{branch}] {count} new commits")
+    };
+    let description = commit_list(&push.commits, count, 10);
+    let url = if push.compare_url.is_empty() {
+        &push.repository.html_url
+    } else {
+        &push.compare_url
+    };
+    EmbedData::new(&title, &description, url, BLUE, "Gitea", author, vec![])
+}
+
+fn render_pull_request(e: &PullRequestEvent, author: EmbedAuthor) -> EmbedData {
+    let pr = &e.pull_request;
+    let (action, color) = match e.action.as_str() {
+        "closed" if pr.merged => ("merged", PURPLE),
+        "closed" => ("closed", RED),
+        "opened" | "reopened" => (e.action.as_str(), GREEN),
+        "synchronized" => ("updated", BLUE),
+        action => (action, BLUE),
+    };
+    EmbedData::new(
+        &format!(
+            "[{}] Pull request {}: #{} {}",
+            e.repository.full_name, action, pr.number, pr.title
+        ),
+        &summary(pr.body.as_deref()),
+        &pr.html_url,
+        color,
+        "Gitea",
+        author,
+        vec![],
+    )
+}
+
+fn render_issue(e: &IssuesEvent, author: EmbedAuthor) -> EmbedData {
+    let issue = &e.issue;
+    let color = match e.action.as_str() {
+        "opened" | "reopened" => GREEN,
+        "closed" => RED,
+        _ => BLUE,
+    };
+    EmbedData::new(
+        &format!(
+            "[{}] Issue {}: #{} {}",
+            e.repository.full_name, e.action, issue.number, issue.title
+        ),
+        &summary(issue.body.as_deref()),
+        &issue.html_url,
+        color,
+        "Gitea",
+        author,
+        vec![],
+    )
+}
+
+fn render_issue_comment(e: &IssueCommentEvent, author: EmbedAuthor) -> EmbedData {
+    let kind = if e.is_pull { "pull request" } else { "issue" };
+    EmbedData::new(
+        &format!(
+            "[{}] Comment {} on {kind} #{}: {}",
+            e.repository.full_name, e.action, e.issue.number, e.issue.title
+        ),
+        &summary(Some(&e.comment.body)),
+        &e.comment.html_url,
+        GREY,
+        "Gitea",
+        author,
+        vec![],
+    )
+}
+
+fn render_release(e: &ReleaseEvent, author: EmbedAuthor) -> EmbedData {
+    let release = &e.release;
+    let name = match release.name.as_deref() {
+        Some(name) if !name.is_empty() => name,
+        _ => &release.tag_name,
+    };
+    let kind = if release.draft {
+        "Draft"
+    } else if release.prerelease {
+        "Pre-release"
+    } else {
+        "Release"
+    };
+    let url = if release.html_url.is_empty() {
+        &e.repository.html_url
+    } else {
+        &release.html_url
+    };
+    EmbedData::new(
+        &format!("[{}] {kind} {}: {name}", e.repository.full_name, e.action),
+        &summary(release.body.as_deref()),
+        url,
+        if release.prerelease || release.draft { YELLOW } else { GREEN },
+        "Gitea",
+        author,
+        vec![EmbedField::new("Tag", &release.tag_name, true)],
+    )
+}
+
+fn render_ref(e: &RefEvent, action: &str, color: u32, author: EmbedAuthor) -> EmbedData {
+    EmbedData::new(
+        &format!(
+            "[{}] {action} {} {}",
+            e.repository.full_name,
+            e.ref_type,
+            short_ref(&e.git_ref)
+        ),
+        "",
+        &e.repository.html_url,
+        color,
+        "Gitea",
+        author,
+        vec![],
+    )
+}
+
+fn render_package(e: &PackageEvent, author: EmbedAuthor) -> EmbedData {
+    let package = &e.package;
+    let (action, color) = match e.action.as_str() {
+        "created" => ("published", GREEN),
+        "deleted" => ("deleted", RED),
+        action => (action, BLUE),
+    };
+    let (owner, url) = match &package.repository {
+        Some(repository) => (&repository.full_name, &repository.html_url),
+        None => (&package.owner.login, &package.owner.html_url),
+    };
+    EmbedData::new(
+        &format!("[{owner}] Package {action}: {} {}", package.name, package.version),
+        "",
+        if package.html_url.is_empty() { url } else { &package.html_url },
+        color,
+        "Gitea",
+        author,
+        vec![EmbedField::new("Type", &package.package_type, true)],
+    )
+}
+
+/// Gitea and Forgejo webhooks, authenticated with the `X-Gitea-Signature` header
+pub struct Gitea;
+
+impl Source for Gitea {
+    fn name(&self) -> &'static str { "gitea" }
+
+    fn detect(&self, request: &Incoming) -> bool { request.header("X-Gitea-Event").is_some() }
+
+    fn authenticate(&self, app: &AppCollection, request: &Incoming) -> Option<bool> {
+        Some(match (&app.secret, request.header("X-Gitea-Signature")) {
+            (Some(secret), Some(signature)) => {
+                signature::verify_sha256_hex(secret, request.body, signature)
+            }
+            _ => false,
+        })
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let event = request.header("X-Gitea-Event").unwrap_or_default();
+        Ok(GiteaEvent::parse(event, request.body)?.map(|e| Box::new(e) as Box<dyn Embed + Send>))
+    }
+}
@@ -0,0 +1,339 @@
+use crate::body_type::{commit_list, short_ref, summary, Commit, Embed, EmbedAuthor, EmbedData};
+use crate::body_type::EmbedField;
+use crate::signature;
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+
+const GREEN: u32 = 0x2ea44f;
+const RED: u32 = 0xcb2431;
+const PURPLE: u32 = 0x6f42c1;
+const BLUE: u32 = 0x0366d6;
+const YELLOW: u32 = 0xdbab09;
+const GREY: u32 = 0x6a737d;
+
+#[derive(Deserialize, Debug)]
+pub struct User {
+    login: String,
+    avatar_url: String,
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Repository {
+    full_name: String,
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    compare: String,
+    #[serde(default)]
+    created: bool,
+    #[serde(default)]
+    deleted: bool,
+    #[serde(default)]
+    forced: bool,
+    commits: Vec<Commit>,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PullRequest {
+    number: u64,
+    title: String,
+    html_url: String,
+    body: Option<String>,
+    #[serde(default)]
+    merged: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PullRequestEvent {
+    action: String,
+    pull_request: PullRequest,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Issue {
+    number: u64,
+    title: String,
+    html_url: String,
+    body: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssuesEvent {
+    action: String,
+    issue: Issue,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Release {
+    tag_name: String,
+    name: Option<String>,
+    html_url: String,
+    body: Option<String>,
+    #[serde(default)]
+    prerelease: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReleaseEvent {
+    action: String,
+    release: Release,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WorkflowRun {
+    /// GitHub documents the name, branch and status as nullable
+    name: Option<String>,
+    head_branch: Option<String>,
+    run_number: u64,
+    status: Option<String>,
+    conclusion: Option<String>,
+    html_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct WorkflowRunEvent {
+    action: String,
+    workflow_run: WorkflowRun,
+    repository: Repository,
+    sender: User,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PingEvent {
+    zen: String,
+    hook_id: u64,
+    repository: Option<Repository>,
+    sender: User,
+}
+
+/// A GitHub webhook payload, picked using the `X-GitHub-Event` header
+#[derive(Debug)]
+pub enum GithubEvent {
+    Push(PushEvent),
+    PullRequest(PullRequestEvent),
+    Issues(IssuesEvent),
+    Release(ReleaseEvent),
+    WorkflowRun(WorkflowRunEvent),
+    Ping(PingEvent),
+}
+
+impl GithubEvent {
+    /// Parse the body for the given event name, events we don't render give `Ok(None)`
+    pub fn parse(event: &str, body: &[u8]) -> Result<Option<GithubEvent>, serde_json::Error> {
+        Ok(Some(match event {
+            "push" => GithubEvent::Push(serde_json::from_slice(body)?),
+            "pull_request" => GithubEvent::PullRequest(serde_json::from_slice(body)?),
+            "issues" => GithubEvent::Issues(serde_json::from_slice(body)?),
+            "release" => GithubEvent::Release(serde_json::from_slice(body)?),
+            "workflow_run" => GithubEvent::WorkflowRun(serde_json::from_slice(body)?),
+            "ping" => GithubEvent::Ping(serde_json::from_slice(body)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn sender(&self) -> &User {
+        match self {
+            GithubEvent::Push(e) => &e.sender,
+            GithubEvent::PullRequest(e) => &e.sender,
+            GithubEvent::Issues(e) => &e.sender,
+            GithubEvent::Release(e) => &e.sender,
+            GithubEvent::WorkflowRun(e) => &e.sender,
+            GithubEvent::Ping(e) => &e.sender,
+        }
+    }
+
+    fn author(&self) -> EmbedAuthor {
+        let sender = self.sender();
+        EmbedAuthor::new(&sender.login, &sender.html_url, &sender.avatar_url)
+    }
+
+    fn render(&self) -> EmbedData {
+        match self {
+            GithubEvent::Push(e) => render_push(e, self.author()),
+            GithubEvent::PullRequest(e) => {
+                let pr = &e.pull_request;
+                let (action, color) = match e.action.as_str() {
+                    "closed" if pr.merged => ("merged", PURPLE),
+                    "closed" => ("closed", RED),
+                    "opened" | "reopened" => (e.action.as_str(), GREEN),
+                    action => (action, BLUE),
+                };
+                EmbedData::new(
+                    &format!(
+                        "[{}] Pull request {}: #{} {}",
+                        e.repository.full_name, action, pr.number, pr.title
+                    ),
+                    &summary(pr.body.as_deref()),
+                    &pr.html_url,
+                    color,
+                    "GitHub",
+                    self.author(),
+                    vec![],
+                )
+            }
+            GithubEvent::Issues(e) => {
+                let issue = &e.issue;
+                let color = match e.action.as_str() {
+                    "opened" | "reopened" => GREEN,
+                    "closed" => RED,
+                    _ => BLUE,
+                };
+                EmbedData::new(
+                    &format!(
+                        "[{}] Issue {}: #{} {}",
+                        e.repository.full_name, e.action, issue.number, issue.title
+                    ),
+                    &summary(issue.body.as_deref()),
+                    &issue.html_url,
+                    color,
+                    "GitHub",
+                    self.author(),
+                    vec![],
+                )
+            }
+            GithubEvent::Release(e) => {
+                let release = &e.release;
+                let name = release.name.as_deref().unwrap_or(&release.tag_name);
+                EmbedData::new(
+                    &format!(
+                        "[{}] Release {}: {}",
+                        e.repository.full_name, e.action, name
+                    ),
+                    &summary(release.body.as_deref()),
+                    &release.html_url,
+                    if release.prerelease { YELLOW } else { GREEN },
+                    "GitHub",
+                    self.author(),
+                    vec![
+                        EmbedField::new("Tag", &release.tag_name, true),
+                        EmbedField::new(
+                            "Pre-release",
+                            if release.prerelease { "Yes" } else { "No" },
+                            true,
+                        ),
+                    ],
+                )
+            }
+            GithubEvent::WorkflowRun(e) => {
+                let run = &e.workflow_run;
+                let conclusion = run
+                    .conclusion
+                    .as_deref()
+                    .or(run.status.as_deref())
+                    .unwrap_or("queued");
+                let color = match conclusion {
+                    "success" => GREEN,
+                    "failure" | "timed_out" | "startup_failure" => RED,
+                    "cancelled" | "skipped" | "neutral" => GREY,
+                    _ => YELLOW,
+                };
+                EmbedData::new(
+                    &format!(
+                        "[{}] {} #{} {}",
+                        e.repository.full_name,
+                        run.name.as_deref().unwrap_or("Workflow"),
+                        run.run_number,
+                        conclusion
+                    ),
+                    &match &run.head_branch {
+                        Some(branch) => format!("Workflow run {} on `{branch}`", e.action),
+                        None => format!("Workflow run {}", e.action),
+                    },
+                    &run.html_url,
+                    color,
+                    "GitHub",
+                    self.author(),
+                    vec![],
+                )
+            }
+            GithubEvent::Ping(e) => {
+                let (title, url) = match &e.repository {
+                    Some(repository) => (
+                        format!("[{}] Webhook {} connected", repository.full_name, e.hook_id),
+                        repository.html_url.as_str(),
+                    ),
+                    None => (format!("Webhook {} connected", e.hook_id), ""),
+                };
+                EmbedData::new(&title, &e.zen, url, GREY, "GitHub", self.author(), vec![])
+            }
+        }
+    }
+}
+
+impl Embed for GithubEvent {
+    fn get_username(&self) -> String { self.sender().login.clone() }
+
+    fn get_avatar_url(&self) -> String { self.sender().avatar_url.clone() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { vec![self.render()] }
+}
+
+fn render_push(push: &PushEvent, author: EmbedAuthor) -> EmbedData {
+    let branch = short_ref(&push.git_ref);
+    let repository = &push.repository.full_name;
+    if push.deleted {
+        return EmbedData::new(
+            &format!("[{repository}] Deleted {branch}"),
+            "",
+            &push.repository.html_url,
+            RED,
+            "GitHub",
+            author,
+            vec![],
+        );
+    }
+    let count = push.commits.len();
+    let title = match (push.created, count) {
+        (true, 0) => format!("[{repository}] Created {branch}"),
+        (_, 1) => format!("[{repository}:{branch}] 1 new commit"),
+        _ => format!("[{repository}:{branch}] {count} new commits"),
+    };
+    let description = commit_list(&push.commits, count, 7);
+    EmbedData::new(
+        &title,
+        &description,
+        &push.compare,
+        if push.forced { RED } else { BLUE },
+        if push.forced { "GitHub - force pushed" } else { "GitHub" },
+        author,
+        vec![],
+    )
+}
+
+/// GitHub webhooks, authenticated with the `X-Hub-Signature-256` header using the apps secret
+pub struct Github;
+
+impl Source for Github {
+    fn name(&self) -> &'static str { "github" }
+
+    fn detect(&self, request: &Incoming) -> bool { request.header("X-GitHub-Event").is_some() }
+
+    fn authenticate(&self, app: &AppCollection, request: &Incoming) -> Option<bool> {
+        Some(match (&app.secret, request.header("X-Hub-Signature-256")) {
+            (Some(secret), Some(signature)) => signature
+                .strip_prefix("sha256=")
+                .map(|signature| signature::verify_sha256_hex(secret, request.body, signature))
+                .unwrap_or(false),
+            _ => false,
+        })
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let event = request.header("X-GitHub-Event").unwrap_or_default();
+        Ok(GithubEvent::parse(event, request.body)?.map(|e| Box::new(e) as Box<dyn Embed + Send>))
+    }
+}
@@ -0,0 +1,338 @@
+use crate::body_type::{commit_list, short_ref, summary, Commit, Embed, EmbedAuthor, EmbedData};
+use crate::body_type::EmbedField;
+use crate::signature;
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+
+const GREEN: u32 = 0x108548;
+const RED: u32 = 0xdd2b0e;
+const PURPLE: u32 = 0x6b4fbb;
+const BLUE: u32 = 0x1f75cb;
+const YELLOW: u32 = 0xab6100;
+const GREY: u32 = 0x737278;
+
+/// The `after` sha GitLab sends when a branch or tag is removed
+const NULL_SHA: &str = "0000000000000000000000000000000000000000";
+
+#[derive(Deserialize, Debug)]
+pub struct User {
+    name: String,
+    username: String,
+    #[serde(default)]
+    avatar_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Project {
+    path_with_namespace: String,
+    web_url: String,
+}
+
+/// Sent for both `Push Hook` and `Tag Push Hook`
+#[derive(Deserialize, Debug)]
+pub struct PushEvent {
+    #[serde(rename = "ref")]
+    git_ref: String,
+    after: String,
+    user_name: String,
+    user_username: String,
+    #[serde(default)]
+    user_avatar: Option<String>,
+    project: Project,
+    #[serde(default)]
+    commits: Vec<Commit>,
+    #[serde(default)]
+    total_commits_count: usize,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MergeRequestAttributes {
+    iid: u64,
+    title: String,
+    url: String,
+    description: Option<String>,
+    state: String,
+    action: Option<String>,
+    source_branch: String,
+    target_branch: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MergeRequestEvent {
+    user: User,
+    project: Project,
+    object_attributes: MergeRequestAttributes,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PipelineAttributes {
+    id: u64,
+    #[serde(rename = "ref")]
+    git_ref: String,
+    sha: String,
+    status: String,
+    duration: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Build {
+    name: String,
+    stage: String,
+    status: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PipelineEvent {
+    user: User,
+    project: Project,
+    object_attributes: PipelineAttributes,
+    #[serde(default)]
+    builds: Vec<Build>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NoteAttributes {
+    note: String,
+    noteable_type: String,
+    url: String,
+}
+
+/// The issue or merge request a note was left on
+#[derive(Deserialize, Debug)]
+pub struct Noteable {
+    iid: u64,
+    title: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NoteEvent {
+    user: User,
+    project: Project,
+    object_attributes: NoteAttributes,
+    merge_request: Option<Noteable>,
+    issue: Option<Noteable>,
+}
+
+/// A GitLab webhook payload, picked using the `X-Gitlab-Event` header
+#[derive(Debug)]
+pub enum GitlabEvent {
+    Push(PushEvent),
+    TagPush(PushEvent),
+    MergeRequest(MergeRequestEvent),
+    Pipeline(PipelineEvent),
+    Note(NoteEvent),
+}
+
+impl GitlabEvent {
+    /// Parse the body for the given event name, events we don't render give `Ok(None)`
+    pub fn parse(event: &str, body: &[u8]) -> Result<Option<GitlabEvent>, serde_json::Error> {
+        Ok(Some(match event {
+            "Push Hook" => GitlabEvent::Push(serde_json::from_slice(body)?),
+            "Tag Push Hook" => GitlabEvent::TagPush(serde_json::from_slice(body)?),
+            "Merge Request Hook" => GitlabEvent::MergeRequest(serde_json::from_slice(body)?),
+            "Pipeline Hook" => GitlabEvent::Pipeline(serde_json::from_slice(body)?),
+            "Note Hook" => GitlabEvent::Note(serde_json::from_slice(body)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn author(&self) -> EmbedAuthor {
+        match self {
+            GitlabEvent::Push(e) | GitlabEvent::TagPush(e) => EmbedAuthor::new(
+                &e.user_name,
+                "",
+                e.user_avatar.as_deref().unwrap_or(""),
+            ),
+            GitlabEvent::MergeRequest(MergeRequestEvent { user, .. })
+            | GitlabEvent::Pipeline(PipelineEvent { user, .. })
+            | GitlabEvent::Note(NoteEvent { user, .. }) => {
+                EmbedAuthor::new(&user.name, "", user.avatar_url.as_deref().unwrap_or(""))
+            }
+        }
+    }
+
+    fn render(&self) -> EmbedData {
+        match self {
+            GitlabEvent::Push(e) => render_push(e, self.author()),
+            GitlabEvent::TagPush(e) => render_tag_push(e, self.author()),
+            GitlabEvent::MergeRequest(e) => render_merge_request(e, self.author()),
+            GitlabEvent::Pipeline(e) => render_pipeline(e, self.author()),
+            GitlabEvent::Note(e) => render_note(e, self.author()),
+        }
+    }
+}
+
+impl Embed for GitlabEvent {
+    fn get_username(&self) -> String {
+        match self {
+            GitlabEvent::Push(e) | GitlabEvent::TagPush(e) => e.user_username.clone(),
+            GitlabEvent::MergeRequest(MergeRequestEvent { user, .. })
+            | GitlabEvent::Pipeline(PipelineEvent { user, .. })
+            | GitlabEvent::Note(NoteEvent { user, .. }) => user.username.clone(),
+        }
+    }
+
+    fn get_avatar_url(&self) -> String { self.author().icon_url.unwrap_or_default() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { vec![self.render()] }
+}
+
+fn render_push(push: &PushEvent, author: EmbedAuthor) -> EmbedData {
+    let branch = short_ref(&push.git_ref);
+    let project = &push.project.path_with_namespace;
+    if push.after == NULL_SHA {
+        return EmbedData::new(
+            &format!("[{project}] Deleted branch {branch}"),
+            "",
+            &push.project.web_url,
+            RED,
+            "GitLab",
+            author,
+            vec![],
+        );
+    }
+    let count = push.total_commits_count.max(push.commits.len());
+    let title = if count == 1 {
+        format!("[{project}:{branch}] 1 new commit")
+    } else {
+        format!("[{project}:{branch}] {count} new commits")
+    };
+    let description = commit_list(&push.commits, count, 8);
+    EmbedData::new(
+        &title,
+        &description,
+        &format!("{}/-/tree/{branch}", push.project.web_url),
+        BLUE,
+        "GitLab",
+        author,
+        vec![],
+    )
+}
+
+fn render_tag_push(push: &PushEvent, author: EmbedAuthor) -> EmbedData {
+    let tag = short_ref(&push.git_ref);
+    let project = &push.project.path_with_namespace;
+    let (title, color) = if push.after == NULL_SHA {
+        (format!("[{project}] Deleted tag {tag}"), RED)
+    } else {
+        (format!("[{project}] Pushed tag {tag}"), GREEN)
+    };
+    EmbedData::new(
+        &title,
+        "",
+        &format!("{}/-/tags/{tag}", push.project.web_url),
+        color,
+        "GitLab",
+        author,
+        vec![],
+    )
+}
+
+fn render_merge_request(e: &MergeRequestEvent, author: EmbedAuthor) -> EmbedData {
+    let mr = &e.object_attributes;
+    let action = mr.action.as_deref().unwrap_or(&mr.state);
+    let color = match action {
+        "merge" | "merged" => PURPLE,
+        "close" | "closed" => RED,
+        "open" | "reopen" | "opened" => GREEN,
+        _ => BLUE,
+    };
+    EmbedData::new(
+        &format!(
+            "[{}] Merge request {action}: !{} {}",
+            e.project.path_with_namespace, mr.iid, mr.title
+        ),
+        &summary(mr.description.as_deref()),
+        &mr.url,
+        color,
+        "GitLab",
+        author,
+        vec![EmbedField::new(
+            "Branches",
+            &format!("`{}` → `{}`", mr.source_branch, mr.target_branch),
+            false,
+        )],
+    )
+}
+
+fn render_pipeline(e: &PipelineEvent, author: EmbedAuthor) -> EmbedData {
+    let pipeline = &e.object_attributes;
+    let color = match pipeline.status.as_str() {
+        "success" => GREEN,
+        "failed" => RED,
+        "canceled" | "skipped" => GREY,
+        _ => YELLOW,
+    };
+    let mut stages: Vec<(String, Vec<String>)> = vec![];
+    for build in &e.builds {
+        let line = format!("{}: {}", build.name, build.status);
+        match stages.iter_mut().find(|(stage, _)| stage == &build.stage) {
+            Some((_, lines)) => lines.push(line),
+            None => stages.push((build.stage.clone(), vec![line])),
+        }
+    }
+    let fields = stages
+        .iter()
+        .map(|(stage, lines)| EmbedField::new(stage, &lines.join("\n"), true))
+        .collect();
+    let mut description = format!(
+        "`{}` on `{}`",
+        pipeline.sha.chars().take(8).collect::<String>(),
+        pipeline.git_ref
+    );
+    if let Some(duration) = pipeline.duration {
+        description.push_str(&format!(" in {duration}s"));
+    }
+    EmbedData::new(
+        &format!(
+            "[{}] Pipeline #{} {}",
+            e.project.path_with_namespace, pipeline.id, pipeline.status
+        ),
+        &description,
+        &format!("{}/-/pipelines/{}", e.project.web_url, pipeline.id),
+        color,
+        "GitLab",
+        author,
+        fields,
+    )
+}
+
+fn render_note(e: &NoteEvent, author: EmbedAuthor) -> EmbedData {
+    let note = &e.object_attributes;
+    let target = match (&e.merge_request, &e.issue) {
+        (Some(mr), _) => format!("merge request !{} {}", mr.iid, mr.title),
+        (_, Some(issue)) => format!("issue #{} {}", issue.iid, issue.title),
+        _ => note.noteable_type.to_lowercase(),
+    };
+    EmbedData::new(
+        &format!("[{}] Comment on {target}", e.project.path_with_namespace),
+        &summary(Some(&note.note)),
+        &note.url,
+        GREY,
+        "GitLab",
+        author,
+        vec![],
+    )
+}
+
+/// GitLab webhooks, authenticated with the `X-Gitlab-Token` header matching the apps secret
+pub struct Gitlab;
+
+impl Source for Gitlab {
+    fn name(&self) -> &'static str { "gitlab" }
+
+    fn detect(&self, request: &Incoming) -> bool { request.header("X-Gitlab-Event").is_some() }
+
+    fn authenticate(&self, app: &AppCollection, request: &Incoming) -> Option<bool> {
+        Some(match (&app.secret, request.header("X-Gitlab-Token")) {
+            (Some(secret), Some(token)) => signature::verify_token(secret, token),
+            _ => false,
+        })
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let event = request.header("X-Gitlab-Event").unwrap_or_default();
+        Ok(GitlabEvent::parse(event, request.body)?.map(|e| Box::new(e) as Box<dyn Embed + Send>))
+    }
+}
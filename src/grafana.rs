@@ -0,0 +1,175 @@
+use crate::body_type::{non_empty, summary, Embed, EmbedAuthor, EmbedData, EmbedField};
+use crate::body_type::{chunk_embeds, MessageData, MessageKey, MAX_FIELDS};
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+const RED: u32 = 0xe02f44;
+const GREEN: u32 = 0x1b855e;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    status: String,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    annotations: BTreeMap<String, String>,
+    #[serde(default)]
+    starts_at: String,
+    #[serde(default)]
+    ends_at: String,
+    #[serde(rename = "generatorURL", default)]
+    generator_url: String,
+    #[serde(rename = "silenceURL", default)]
+    silence_url: String,
+    #[serde(rename = "dashboardURL", default)]
+    dashboard_url: String,
+    #[serde(rename = "panelURL", default)]
+    panel_url: String,
+    #[serde(default)]
+    value_string: String,
+}
+
+/// Grafana's unified alerting webhook payload
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GrafanaWebhook {
+    receiver: String,
+    status: String,
+    org_id: u64,
+    alerts: Vec<Alert>,
+    #[serde(default)]
+    group_key: String,
+    #[serde(default)]
+    truncated_alerts: u64,
+    #[serde(default)]
+    title: String,
+    #[serde(rename = "externalURL", default)]
+    external_url: String,
+}
+
+impl GrafanaWebhook {
+    fn is_resolved(&self) -> bool { self.status == "resolved" }
+
+    fn footer(&self) -> String { format!("Grafana - {} (org {})", self.receiver, self.org_id) }
+}
+
+impl Alert {
+    fn is_firing(&self) -> bool { self.status == "firing" }
+
+    fn render(&self, external_url: &str, footer: &str) -> EmbedData {
+        let name = self
+            .labels
+            .get("alertname")
+            .map(String::as_str)
+            .unwrap_or("Alert");
+        let title = if self.is_firing() {
+            format!("[FIRING] {name}")
+        } else {
+            format!("[RESOLVED] {name}")
+        };
+        let description = self
+            .annotations
+            .get("summary")
+            .or_else(|| self.annotations.get("description"))
+            .map(|text| summary(Some(text)))
+            .unwrap_or_default();
+        let links = [
+            ("Panel", self.panel_url.as_str()),
+            ("Dashboard", self.dashboard_url.as_str()),
+            // There's nothing to silence once it has resolved
+            ("Silence", if self.is_firing() { self.silence_url.as_str() } else { "" }),
+        ]
+        .iter()
+        .filter(|(_, url)| !url.is_empty())
+        .map(|(name, url)| format!("[{name}]({url})"))
+        .collect::<Vec<String>>()
+        .join(" | ");
+        let mut fields = vec![];
+        if !self.value_string.is_empty() {
+            fields.push(EmbedField::new(
+                "Values",
+                &format!("`{}`", summary(Some(&self.value_string))),
+                false,
+            ));
+        }
+        fields.extend(
+            self.labels
+                .iter()
+                .filter(|(name, _)| !matches!(name.as_str(), "alertname" | "__alert_rule_uid__"))
+                .map(|(name, value)| EmbedField::new(name, value, true)),
+        );
+        if !links.is_empty() {
+            fields.push(EmbedField::new("Links", &links, false));
+        }
+        let mut embed = EmbedData::new(
+            &title,
+            &description,
+            &self.generator_url,
+            if self.is_firing() { RED } else { GREEN },
+            footer,
+            EmbedAuthor::new("Grafana", external_url, ""),
+            fields.into_iter().take(MAX_FIELDS).collect(),
+        );
+        embed.timestamp = non_empty(if self.is_firing() {
+            &self.starts_at
+        } else {
+            &self.ends_at
+        });
+        embed
+    }
+}
+
+impl Embed for GrafanaWebhook {
+    fn get_username(&self) -> String { self.receiver.clone() }
+
+    fn get_avatar_url(&self) -> String { "".into() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> {
+        let footer = self.footer();
+        self.alerts
+            .iter()
+            .map(|alert| alert.render(&self.external_url, &footer))
+            .collect()
+    }
+
+    /// Posts as many alerts as fit in one message, the rest are only counted
+    fn get_message(&self) -> MessageData {
+        let embeds = chunk_embeds(self.get_embeds()).into_iter().next().unwrap_or_default();
+        let hidden = (self.alerts.len() - embeds.len()) as u64 + self.truncated_alerts;
+        let mut content = self.title.clone();
+        if hidden > 0 {
+            content.push_str(&format!("\n...and {hidden} more"));
+        }
+        MessageData {
+            content,
+            ..MessageData::from_embeds(embeds)
+        }
+    }
+
+    fn get_message_key(&self) -> Option<MessageKey> {
+        non_empty(&self.group_key).map(|key| MessageKey::new(&key, self.is_resolved()))
+    }
+}
+
+/// Grafana alerting webhooks, authenticated with the app token
+pub struct Grafana;
+
+impl Source for Grafana {
+    fn name(&self) -> &'static str { "grafana" }
+
+    fn detect(&self, request: &Incoming) -> bool {
+        request
+            .header("User-Agent")
+            .map(|agent| agent.starts_with("Grafana"))
+            .unwrap_or(false)
+            || serde_json::from_slice::<GrafanaWebhook>(request.body).is_ok()
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let webhook: GrafanaWebhook = serde_json::from_slice(request.body)?;
+        Ok(Some(Box::new(webhook)))
+    }
+}
@@ -0,0 +1,203 @@
+use crate::body_type::{AllowedMentions, Destination, EmbedAuthor, EmbedData, MessageData};
+use crate::{get_approved_app, send_message, AppCollection, SendEmbed, UserCollection};
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, DateTime};
+use mongodb::Database;
+use std::time::Duration;
+
+const RED: u32 = 0xd73a49;
+const GREEN: u32 = 0x2ea44f;
+
+/// How often apps are checked for missed heartbeats, in seconds
+const CHECK_INTERVAL: u64 = 60;
+
+/// Record that an app has been heard from, posting a recovery notice if it had gone quiet
+pub async fn beat(state: &SendEmbed, db: &Database, app: &AppCollection, user: &UserCollection) {
+    if app.heartbeat_interval.is_none() {
+        return;
+    }
+    let app_coll = db.collection::<AppCollection>("application");
+    let app_id = app.app_id as i64;
+    if let Err(e) = app_coll
+        .update_one(
+            doc! {"app_id": app_id},
+            doc! {"$set": {"last_seen": DateTime::now()}},
+            None,
+        )
+        .await
+    {
+        eprintln!("Failed to record heartbeat for app {}: {e}", app.app_id);
+        return;
+    }
+    if !app.heartbeat_missed {
+        return;
+    }
+    // Only the first check-in after a miss posts the notice
+    let recovered = app_coll
+        .update_one(
+            doc! {"app_id": app_id, "heartbeat_missed": true},
+            doc! {"$set": {"heartbeat_missed": false}},
+            None,
+        )
+        .await
+        .map(|result| result.modified_count > 0)
+        .unwrap_or(false);
+    if !recovered {
+        return;
+    }
+    let description = match app.last_seen {
+        Some(last_seen) => {
+            let quiet = (DateTime::now().timestamp_millis() - last_seen.timestamp_millis()) / 1000;
+            format!("Checked in again after {}", format_interval(quiet as u64))
+        }
+        None => "Checked in again".into(),
+    };
+    let embed = EmbedData::new(
+        &format!("{} recovered", app.app_name),
+        &description,
+        "",
+        GREEN,
+        "Heartbeat",
+        EmbedAuthor::new("HookMe", "", ""),
+        vec![],
+    );
+    send_message(state, destination(app, user), MessageData::from_embeds(vec![embed])).await;
+}
+
+/// Check apps with an expected interval and warn their owner when one is missed
+pub async fn watch(state: SendEmbed, db: Database) {
+    let mut interval = tokio::time::interval(Duration::from_secs(CHECK_INTERVAL));
+    let app_coll = db.collection::<AppCollection>("application");
+    loop {
+        interval.tick().await;
+        let apps: Vec<AppCollection> = match app_coll
+            .find(
+                doc! {
+                    "approved": true,
+                    "heartbeat_interval": {"$ne": null},
+                    "heartbeat_missed": {"$ne": true},
+                },
+                None,
+            )
+            .await
+        {
+            Ok(cursor) => cursor.try_collect().await.unwrap_or_default(),
+            Err(e) => {
+                eprintln!("Failed to find apps with heartbeats: {e}");
+                continue;
+            }
+        };
+        let now = DateTime::now().timestamp_millis();
+        for app in apps {
+            let (expected, last_seen) = match (app.heartbeat_interval, app.last_seen) {
+                (Some(expected), Some(last_seen)) => (expected, last_seen),
+                _ => continue,
+            };
+            if now - last_seen.timestamp_millis() < expected as i64 * 1000 {
+                continue;
+            }
+            let missed = app_coll
+                .update_one(
+                    doc! {"app_id": app.app_id as i64, "heartbeat_missed": {"$ne": true}},
+                    doc! {"$set": {"heartbeat_missed": true}},
+                    None,
+                )
+                .await
+                .map(|result| result.modified_count > 0)
+                .unwrap_or(false);
+            if !missed {
+                continue;
+            }
+            if let Some((app, user)) = get_approved_app(&db, app.app_id as i64).await {
+                let message = warning(&app, &user, expected, last_seen);
+                send_message(&state, destination(&app, &user), message).await;
+            }
+        }
+    }
+}
+
+/// A warning that pings the owner, who is the only one it's allowed to mention
+fn warning(
+    app: &AppCollection,
+    user: &UserCollection,
+    expected: u64,
+    last_seen: DateTime,
+) -> MessageData {
+    let embed = EmbedData::new(
+        &format!("{} missed its heartbeat", app.app_name),
+        &format!(
+            "Expected every {}, last heard from <t:{}:R>",
+            format_interval(expected),
+            last_seen.timestamp_millis() / 1000
+        ),
+        "",
+        RED,
+        "Heartbeat",
+        EmbedAuthor::new("HookMe", "", ""),
+        vec![],
+    );
+    MessageData {
+        content: format!("<@{}>", user.id),
+        allowed_mentions: Some(AllowedMentions {
+            users: vec![user.id.to_string()],
+            ..Default::default()
+        }),
+        ..MessageData::from_embeds(vec![embed])
+    }
+}
+
+fn destination(app: &AppCollection, user: &UserCollection) -> Destination {
+    Destination::new(
+        &app.app_name,
+        "",
+        app.server_id,
+        app.channel_id,
+        user.id,
+        app.app_id,
+    )
+}
+
+/// Parse an interval like `26h`, `90m` or `1d12h` into seconds
+pub fn parse_interval(input: &str) -> Option<u64> {
+    let mut total = 0;
+    let mut number = String::new();
+    for c in input.trim().chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c.to_ascii_lowercase() {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return None,
+        };
+        let part = number.parse::<u64>().ok()?.checked_mul(unit)?;
+        total = part.checked_add(total)?;
+        number.clear();
+    }
+    // Intervals are turned into milliseconds and added to timestamps, so keep them in range
+    if !number.is_empty() || total == 0 || total > i64::MAX as u64 / 1000 {
+        return None;
+    }
+    Some(total)
+}
+
+/// Show seconds in the same style [`parse_interval`] reads
+pub fn format_interval(secs: u64) -> String {
+    let units = [("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60), ("s", 1)];
+    let mut rest = secs;
+    let mut output = String::new();
+    for (name, size) in units {
+        if rest >= size {
+            output.push_str(&format!("{}{name}", rest / size));
+            rest %= size;
+        }
+    }
+    if output.is_empty() {
+        "0s".into()
+    } else {
+        output
+    }
+}
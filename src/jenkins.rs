@@ -0,0 +1,119 @@
+use crate::body_type::{Embed, EmbedAuthor, EmbedData, EmbedField, MessageKey};
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+
+const GREEN: u32 = 0x2ea44f;
+const RED: u32 = 0xd73a49;
+const YELLOW: u32 = 0xdbab09;
+const BLUE: u32 = 0x335061;
+const GREY: u32 = 0x6a737d;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Scm {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    branch: Option<String>,
+    #[serde(default)]
+    commit: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Build {
+    #[serde(default)]
+    full_url: String,
+    number: u64,
+    phase: String,
+    /// Only sent once the build has completed
+    #[serde(default)]
+    status: Option<String>,
+    #[serde(default)]
+    scm: Scm,
+}
+
+/// The Jenkins Notification Plugin's JSON payload
+#[derive(Deserialize, Debug)]
+pub struct JenkinsNotification {
+    name: String,
+    build: Build,
+}
+
+impl JenkinsNotification {
+    fn render(&self) -> EmbedData {
+        let build = &self.build;
+        let state = build.status.as_deref().unwrap_or(&build.phase);
+        let color = match state {
+            "SUCCESS" => GREEN,
+            "FAILURE" => RED,
+            "UNSTABLE" => YELLOW,
+            "ABORTED" | "NOT_BUILT" => GREY,
+            _ => BLUE,
+        };
+        let mut fields = vec![EmbedField::new("Phase", &title_case(&build.phase), true)];
+        if let Some(branch) = &build.scm.branch {
+            fields.push(EmbedField::new("Branch", branch, true));
+        }
+        if let Some(commit) = &build.scm.commit {
+            fields.push(EmbedField::new(
+                "Commit",
+                &format!("`{}`", commit.chars().take(8).collect::<String>()),
+                true,
+            ));
+        }
+        EmbedData::new(
+            &format!("[{}] Build #{} {}", self.name, build.number, title_case(state)),
+            "",
+            &build.full_url,
+            color,
+            "Jenkins",
+            EmbedAuthor::new("Jenkins", build.scm.url.as_deref().unwrap_or(""), ""),
+            fields,
+        )
+    }
+}
+
+impl Embed for JenkinsNotification {
+    fn get_username(&self) -> String { "Jenkins".into() }
+
+    fn get_avatar_url(&self) -> String { "".into() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { vec![self.render()] }
+
+    /// Every phase of a build edits the same message, FINALIZED is the last one sent
+    fn get_message_key(&self) -> Option<MessageKey> {
+        let key = format!("{}#{}", self.name, self.build.number);
+        Some(MessageKey::new(&key, self.build.phase == "FINALIZED"))
+    }
+}
+
+/// Jenkins sends phases and statuses in upper snake case
+fn title_case(input: &str) -> String {
+    input
+        .split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_string() + &chars.as_str().to_lowercase(),
+                None => "".into(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Jenkins Notification Plugin webhooks, authenticated with the app token
+pub struct Jenkins;
+
+impl Source for Jenkins {
+    fn name(&self) -> &'static str { "jenkins" }
+
+    fn detect(&self, request: &Incoming) -> bool {
+        serde_json::from_slice::<JenkinsNotification>(request.body).is_ok()
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let notification: JenkinsNotification = serde_json::from_slice(request.body)?;
+        Ok(Some(Box::new(notification)))
+    }
+}
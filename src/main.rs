@@ -1,30 +1,62 @@
+use attachment::DiscordUpload;
 use axum::{
+    body::Bytes,
     extract::{Extension, Path, Query},
-    http::StatusCode,
-    routing::post,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, patch, post},
     Json, Router,
 };
-use bcrypt::verify;
-use body_type::{Destination, Embed, EmbedData};
+use body_type::{Delivery, Destination, DiscordMessageEdit, Embed, MessageData};
+use signature::SignatureScheme;
+use source::{Incoming, Source};
+use template::EmbedTemplate;
 use mongodb::{
-    bson::doc, bson::oid::ObjectId, bson::Bson, options::ClientOptions, Client, Database,
+    bson::doc, bson::oid::ObjectId, bson::Bson, bson::DateTime, options::ClientOptions, Client,
+    Database,
 };
 use serde::{Deserialize, Serialize};
 use serenity::framework::standard::StandardFramework;
 use serenity::prelude::*;
 use serenity::Client as DS_Client;
 use std::net::SocketAddr;
+use std::time::Duration;
 use std::{error::Error, net::Ipv4Addr, sync::Arc};
+use serenity::http::Http;
+use serenity::model::channel::Message;
 use tokio::sync::mpsc::{channel, Sender};
+use tokio::sync::oneshot;
 use tokio::sync::RwLock;
 use tower::ServiceBuilder;
+use tower_http::limit::RequestBodyLimitLayer;
 
 use discord::Handler;
 
+mod alertmanager;
+mod attachment;
 mod body_type;
 mod discord;
+mod drone;
+mod feed;
+mod gitea;
+mod github;
+mod gitlab;
+mod grafana;
+mod heartbeat;
+mod jenkins;
+mod registry;
+mod sentry;
+mod signature;
+mod slack;
+mod smtp;
+mod source;
+mod template;
+mod token;
 
-type SendEmbed = Arc<RwLock<Sender<(Destination, EmbedData)>>>;
+type SendEmbed = Arc<RwLock<Sender<Delivery>>>;
+
+/// How long a `?wait=true` request waits for its message to be posted
+const WAIT_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct UserCollection {
@@ -41,22 +73,87 @@ pub struct UserRef {
     id: ObjectId,
 }
 
+/// A message that can be edited by later payloads sharing its key
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MessageCollection {
+    app_id: u64,
+    key: String,
+    channel_id: u64,
+    message_id: u64,
+}
+
+/// A message an app has posted, so it can later be edited or deleted through the api
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PostedMessageCollection {
+    app_id: u64,
+    channel_id: u64,
+    message_id: u64,
+}
+
+/// A feed polled for an app, with the ids of entries that have already been posted
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FeedCollection {
+    _id: ObjectId,
+    app_id: u64,
+    url: String,
+    seen: Vec<String>,
+}
+
+/// One of an app's tokens, named so each sender can have its own and be revoked on its own
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AppToken {
+    name: String,
+    /// The bcrypt hash of the token
+    hash: String,
+    created: DateTime,
+    #[serde(default)]
+    last_used: Option<DateTime>,
+    #[serde(default)]
+    expires: Option<DateTime>,
+}
+
+impl AppToken {
+    fn is_expired(&self) -> bool { self.expires.is_some_and(|expires| expires <= DateTime::now()) }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AppCollection {
     _id: ObjectId,
     app_id: u64,
     app_name: String,
-    token: String,
+    #[serde(default)]
+    tokens: Vec<AppToken>,
     owner: UserRef,
     server_id: u64,
     channel_id: u64,
     approved: Bson,
+    /// Shared secret used to verify signed webhooks such as GitHub's
+    #[serde(default)]
+    secret: Option<String>,
+    /// Turns arbitrary JSON sent to the `json` route into a message
+    #[serde(default)]
+    template: Option<EmbedTemplate>,
+    /// How often in seconds the app is expected to send something, if it's being watched
+    #[serde(default)]
+    heartbeat_interval: Option<u64>,
+    /// When the app last sent something, only kept for apps with a heartbeat
+    #[serde(default)]
+    last_seen: Option<DateTime>,
+    /// Whether the owner has been warned about a missed heartbeat
+    #[serde(default)]
+    heartbeat_missed: bool,
+    /// Ignore `?token=` and only accept the token from request headers
+    #[serde(default)]
+    header_token_only: bool,
+    /// How the app signs requests with its secret, replacing the token when set
+    #[serde(default)]
+    signature: Option<SignatureScheme>,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().expect("Failed to load .env file");
-    let (sender, receiver) = channel::<(Destination, EmbedData)>(2048);
+    let (sender, receiver) = channel::<Delivery>(2048);
     let mongo_username =
         std::env::var("MONGO_USERNAME").expect("Could not get mongo username in environment");
     let mongo_password = url_encode(
@@ -74,15 +171,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let db = client
         .default_database()
         .expect("Failed to get default database");
+    token::migrate(&db).await?;
     let db_clone = db.clone();
+    let token =
+        std::env::var("DISCORD_TOKEN").expect("Could not find Discord Token in environment");
+    // Used by routes that act on messages directly rather than through the bot
+    let http = Arc::new(Http::new(&token));
 
     // Run Discord Bot
     tokio::spawn(async move {
         let prefix = std::env::var("BOT_PREFIX").unwrap_or_else(|_| "`".into());
         let handler = Handler::new(prefix.chars().next().unwrap(), receiver, db_clone);
         let framework = StandardFramework::new().configure(|c| c.prefix(prefix));
-        let token =
-            std::env::var("DISCORD_TOKEN").expect("Could not find Discord Token in environment");
         let intents = GatewayIntents::GUILD_MESSAGES
             | GatewayIntents::DIRECT_MESSAGES
             | GatewayIntents::MESSAGE_CONTENT;
@@ -97,12 +197,49 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    let app = Router::new()
+    let state: SendEmbed = Arc::new(RwLock::new(sender));
+    // An empty SMTP_PORT is treated like an unset one
+    if let Some(port) = std::env::var("SMTP_PORT").ok().filter(|port| !port.trim().is_empty()) {
+        match port.trim().parse() {
+            Ok(port) => {
+                tokio::spawn(smtp::listen(port, state.clone(), db.clone()));
+            }
+            Err(_) => eprintln!("SMTP_PORT {port} is not a valid port, not accepting email"),
+        }
+    }
+    tokio::spawn(feed::poll(state.clone(), db.clone()));
+    tokio::spawn(heartbeat::watch(state.clone(), db.clone()));
+
+    let mut app = Router::new()
+        .route("/:app_id", post(hook_any))
         .route("/:app_id/discord", post(hook_discord))
-        .layer(
+        .route("/:app_id/heartbeat", get(hook_heartbeat).post(hook_heartbeat))
+        .route(
+            "/:app_id/discord/messages/:message_id",
+            patch(edit_discord).delete(delete_discord),
+        );
+    for source in source::sources() {
+        let route = format!("/:app_id/{}", source.name());
+        app = app.route(
+            &route,
+            post(
+                move |path: Path<i64>,
+                      query: Query<HookQuery>,
+                      headers: HeaderMap,
+                      state: Extension<SendEmbed>,
+                      db: Extension<Database>,
+                      body: Bytes| {
+                    hook_source(source.clone(), path, query, headers, state, db, body)
+                },
+            ),
+        );
+    }
+    let app = app.layer(
             ServiceBuilder::new()
-                .layer(Extension(Arc::new(RwLock::new(sender))))
+                .layer(Extension(state))
                 .layer(Extension(db))
+                .layer(Extension(http))
+                .layer(RequestBodyLimitLayer::new(attachment::max_body_size()))
                 .into_inner(),
         );
     let port = std::env::var("PORT").expect("Could not find port in environment");
@@ -117,58 +254,433 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 #[derive(Deserialize)]
 struct HookQuery {
-    pub(crate) token: String,
+    pub(crate) token: Option<String>,
+    /// Hold the response until the message is posted, like Discord's own `?wait=true`
+    #[serde(default)]
+    pub(crate) wait: bool,
 }
 
 // Webhook handling routes
 /// Discord webhook handling route
 async fn hook_discord(
-    Json(body): Json<body_type::DiscordWebhook>,
     Path(app_id): Path<i64>,
-    // The token has to come in through the URI in one way or another sadly
-    // This is INCREDIBLY unsafe and unsecure, but I can't enforce services
-    // to provide it through more secure means since the intention of the bot
-    // is to work with already existing webhook services just with a custom URI
+    // Services that can't set headers have to send the token in the URI, where it ends up in
+    // proxy and access logs, so apps can turn this off with `header_token_only`
+    Query(query): Query<HookQuery>,
+    headers: HeaderMap,
+    state: Extension<SendEmbed>,
+    db: Extension<Database>,
+    body: Bytes,
+) -> Response {
+    let token = query.token.as_deref();
+    let (coll, user_col) = match authenticated_app(&db, app_id, &headers, &body, token).await {
+        Some(found) => found,
+        None => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    match DiscordUpload::from_body(&headers, body).await {
+        Ok(upload) => post_discord(upload, coll, user_col, query.wait, &state, &db).await,
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Post a Discord webhook for an app that has already been authenticated
+async fn post_discord(
+    upload: DiscordUpload,
+    coll: AppCollection,
+    user_col: UserCollection,
+    wait: bool,
+    state: &SendEmbed,
+    db: &Database,
+) -> Response {
+    let DiscordUpload {
+        webhook: body,
+        attachments,
+    } = upload;
+    // Content-only payloads leave the username out, only a different name is rejected
+    let username = body.get_username();
+    if !username.is_empty() && username != coll.app_name {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    heartbeat::beat(state, db, &coll, &user_col).await;
+    let message = MessageData {
+        attachments,
+        ..body.get_message()
+    };
+    if message.is_empty() {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let destination = Destination::new(
+        &coll.app_name,
+        &body.get_avatar_url(),
+        coll.server_id,
+        coll.channel_id,
+        user_col.id,
+        coll.app_id,
+    );
+    if !wait {
+        send_message(state, destination, message).await;
+        return StatusCode::ACCEPTED.into_response();
+    }
+    match send_message_and_wait(state, destination, message).await {
+        Ok(posted) => Json(posted).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Generic webhook handling route
+///
+/// Works out which source sent the request from its headers and body, anything
+/// unrecognised is treated as a Discord webhook
+async fn hook_any(
+    Path(app_id): Path<i64>,
+    Query(query): Query<HookQuery>,
+    headers: HeaderMap,
+    state: Extension<SendEmbed>,
+    db: Extension<Database>,
+    body: Bytes,
+) -> Response {
+    let (coll, user_col) = match get_approved_app(&db, app_id).await {
+        Some(found) => found,
+        None => return StatusCode::UNAUTHORIZED.into_response(),
+    };
+    // Detection looks at the body, so a signature has to be checked before it
+    if verify_signature(&coll, &headers, &body) == Some(false) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let is_multipart = header_str(&headers, "Content-Type")
+        .map(|content_type| content_type.starts_with("multipart/form-data"))
+        .unwrap_or(false);
+    let incoming = Incoming {
+        headers: &headers,
+        body: &body,
+        token: query.token.as_deref(),
+    };
+    if !is_multipart {
+        if let Some(source) = source::detect(&incoming) {
+            return post_source(source, &coll, &user_col, &incoming, &state, &db)
+                .await
+                .into_response();
+        }
+    }
+    if !authenticate_app(&db, &coll, &headers, &body, query.token.as_deref()).await {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let upload = if is_multipart {
+        DiscordUpload::from_body(&headers, body).await
+    } else {
+        serde_json::from_slice(&body)
+            .map(|webhook| DiscordUpload {
+                webhook,
+                attachments: vec![],
+            })
+            .map_err(|_| StatusCode::BAD_REQUEST)
+    };
+    match upload {
+        Ok(upload) => post_discord(upload, coll, user_col, query.wait, &state, &db).await,
+        Err(status) => status.into_response(),
+    }
+}
+
+/// Webhook handling route for a registered [`Source`]
+async fn hook_source(
+    source: Arc<dyn Source>,
+    Path(app_id): Path<i64>,
     Query(query): Query<HookQuery>,
+    headers: HeaderMap,
     state: Extension<SendEmbed>,
     db: Extension<Database>,
+    body: Bytes,
+) -> StatusCode {
+    let (coll, user_col) = match get_approved_app(&db, app_id).await {
+        Some(app) => app,
+        None => return StatusCode::UNAUTHORIZED,
+    };
+    let incoming = Incoming {
+        headers: &headers,
+        body: &body,
+        token: query.token.as_deref(),
+    };
+    post_source(source, &coll, &user_col, &incoming, &state, &db).await
+}
+
+/// Authenticate and post a request in a [`Source`]'s format
+async fn post_source(
+    source: Arc<dyn Source>,
+    coll: &AppCollection,
+    user_col: &UserCollection,
+    incoming: &Incoming<'_>,
+    state: &SendEmbed,
+    db: &Database,
+) -> StatusCode {
+    // An app's own signature scheme replaces whatever the source would check
+    let authenticated = match verify_signature(coll, incoming.headers, incoming.body)
+        .or_else(|| source.authenticate(coll, incoming))
+    {
+        Some(verified) => verified,
+        None => authenticate_token(db, coll, incoming.headers, incoming.token).await,
+    };
+    if !authenticated {
+        return StatusCode::UNAUTHORIZED;
+    }
+    heartbeat::beat(state, db, coll, user_col).await;
+    match source.parse(coll, incoming) {
+        Ok(Some(parsed)) => {
+            let destination = Destination::new(
+                &coll.app_name,
+                &parsed.get_avatar_url(),
+                coll.server_id,
+                coll.channel_id,
+                user_col.id,
+                coll.app_id,
+            )
+            .with_message_key(parsed.get_message_key());
+            send_message(state, destination, parsed.get_message()).await;
+            StatusCode::ACCEPTED
+        }
+        Ok(None) => StatusCode::NO_CONTENT,
+        Err(e) => {
+            eprintln!("Failed to parse {} webhook: {e}", source.name());
+            StatusCode::BAD_REQUEST
+        }
+    }
+}
+
+/// Check-in route for apps with a heartbeat, nothing is posted
+async fn hook_heartbeat(
+    Path(app_id): Path<i64>,
+    Query(query): Query<HookQuery>,
+    headers: HeaderMap,
+    state: Extension<SendEmbed>,
+    db: Extension<Database>,
+    body: Bytes,
+) -> StatusCode {
+    let token = query.token.as_deref();
+    match authenticated_app(&db, app_id, &headers, &body, token).await {
+        Some((coll, user_col)) => {
+            heartbeat::beat(&state, &db, &coll, &user_col).await;
+            StatusCode::NO_CONTENT
+        }
+        None => StatusCode::UNAUTHORIZED,
+    }
+}
+
+/// Edit a message previously posted through the Discord webhook route
+async fn edit_discord(
+    Path((app_id, message_id)): Path<(i64, u64)>,
+    Query(query): Query<HookQuery>,
+    headers: HeaderMap,
+    db: Extension<Database>,
+    http: Extension<Arc<Http>>,
+    body: Bytes,
+) -> Response {
+    let token = query.token.as_deref();
+    let posted = match get_posted_message(&db, app_id, &headers, &body, token, message_id).await
+    {
+        Ok(posted) => posted,
+        Err(status) => return status.into_response(),
+    };
+    let upload = match DiscordUpload::<DiscordMessageEdit>::from_body(&headers, body).await {
+        Ok(upload) => upload,
+        Err(status) => return status.into_response(),
+    };
+    match discord::edit_posted(&http, &posted, &upload.webhook, &upload.attachments).await {
+        Ok(message) => Json(message).into_response(),
+        Err(e) => {
+            eprintln!("Failed to edit message {message_id}: {e}");
+            StatusCode::BAD_GATEWAY.into_response()
+        }
+    }
+}
+
+/// Delete a message previously posted through the Discord webhook route
+async fn delete_discord(
+    Path((app_id, message_id)): Path<(i64, u64)>,
+    Query(query): Query<HookQuery>,
+    headers: HeaderMap,
+    db: Extension<Database>,
+    http: Extension<Arc<Http>>,
+    body: Bytes,
 ) -> StatusCode {
-    // println!("{:?}", body);
-    // println!("App ID: {}", app_id);
-    // println!("Token: {}", &query.token);
+    let token = query.token.as_deref();
+    let posted = match get_posted_message(&db, app_id, &headers, &body, token, message_id).await
+    {
+        Ok(posted) => posted,
+        Err(status) => return status,
+    };
+    if let Err(e) = discord::delete_posted(&http, &posted).await {
+        eprintln!("Failed to delete message {message_id}: {e}");
+        return StatusCode::BAD_GATEWAY;
+    }
+    if let Err(e) = db
+        .collection::<PostedMessageCollection>("posted_message")
+        .delete_one(
+            doc! {"app_id": app_id, "message_id": message_id as i64},
+            None,
+        )
+        .await
+    {
+        eprintln!("Failed to remove posted message {message_id}: {e}");
+        return StatusCode::INTERNAL_SERVER_ERROR;
+    }
+    StatusCode::NO_CONTENT
+}
+
+/// Authenticate an app and find a message it posted
+async fn get_posted_message(
+    db: &Database,
+    app_id: i64,
+    headers: &HeaderMap,
+    body: &[u8],
+    token: Option<&str>,
+    message_id: u64,
+) -> Result<PostedMessageCollection, StatusCode> {
+    if authenticated_app(db, app_id, headers, body, token).await.is_none() {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    match db
+        .collection::<PostedMessageCollection>("posted_message")
+        .find_one(
+            doc! {"app_id": app_id, "message_id": message_id as i64},
+            None,
+        )
+        .await
+    {
+        Ok(Some(posted)) => Ok(posted),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            eprintln!("Error Occured: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Find an approved app along with the user that owns it
+async fn get_approved_app(db: &Database, app_id: i64) -> Option<(AppCollection, UserCollection)> {
     let collection = db.collection::<AppCollection>("application");
     if let Ok(Some(coll)) = collection
         .find_one(
-            doc! {"app_id": app_id, "app_name": &body.get_username(), "approved": Bson::Boolean(true)},
+            doc! {"app_id": app_id, "approved": Bson::Boolean(true)},
             None,
         )
         .await
     {
         let collection = db.collection::<UserCollection>("user");
-        match collection.find_one(doc!{"_id": coll.owner.id}, None).await {
-            Ok(Some(user_col)) => {
-                if verify(&query.token, &coll.token).is_ok() {
-                    let destination = Destination::new(
-                        &body.get_username(),
-                        &body.get_avatar_url(),
-                        coll.server_id,
-                        coll.channel_id,
-                        user_col.id,
-                        coll.app_id,
-                    );
-                    let lock = state.write().await;
-                    lock.send((destination, body.get_first_embed()))
-                        .await
-                        .expect("Failed to send embed");
-                    drop(lock);
-                    return StatusCode::ACCEPTED;
-                }
-            },
+        match collection.find_one(doc! {"_id": coll.owner.id}, None).await {
+            Ok(Some(user_col)) => return Some((coll, user_col)),
             Ok(None) => eprintln!("No user found"),
             Err(e) => eprintln!("Error Occured: {}", e),
         }
     }
-    StatusCode::UNAUTHORIZED
+    None
+}
+
+/// Find an approved app and check the request really came from it
+async fn authenticated_app(
+    db: &Database,
+    app_id: i64,
+    headers: &HeaderMap,
+    body: &[u8],
+    query: Option<&str>,
+) -> Option<(AppCollection, UserCollection)> {
+    let (app, user) = get_approved_app(db, app_id).await?;
+    if !authenticate_app(db, &app, headers, body, query).await {
+        return None;
+    }
+    Some((app, user))
+}
+
+/// Check a request by its signature when the app has a scheme, or by its token otherwise
+///
+/// `query` is the `?token=` query, which is ignored for apps that only accept headers
+async fn authenticate_app(
+    db: &Database,
+    app: &AppCollection,
+    headers: &HeaderMap,
+    body: &[u8],
+    query: Option<&str>,
+) -> bool {
+    match verify_signature(app, headers, body) {
+        Some(verified) => verified,
+        None => authenticate_token(db, app, headers, query).await,
+    }
+}
+
+/// Check the token a request was sent with, from its headers or the `?token=` query
+async fn authenticate_token(
+    db: &Database,
+    app: &AppCollection,
+    headers: &HeaderMap,
+    query: Option<&str>,
+) -> bool {
+    let token = header_token(headers).or_else(|| {
+        query
+            .filter(|_| !app.header_token_only)
+            .map(String::from)
+    });
+    token::authenticate(db, app, token.as_deref()).await
+}
+
+/// Check a request against the app's signature scheme, `None` when it doesn't have one
+fn verify_signature(app: &AppCollection, headers: &HeaderMap, body: &[u8]) -> Option<bool> {
+    let scheme = app.signature.as_ref()?;
+    Some(
+        app.secret
+            .as_deref()
+            .map(|secret| scheme.verify(secret, headers, body))
+            .unwrap_or(false),
+    )
+}
+
+/// Find a token in `Authorization: Bearer`, `X-HookMe-Token` or a Basic auth password
+fn header_token(headers: &HeaderMap) -> Option<String> {
+    if let Some(token) = header_str(headers, "X-HookMe-Token") {
+        return Some(token.trim().to_string());
+    }
+    let (scheme, credentials) = header_str(headers, "Authorization")?.trim().split_once(' ')?;
+    if scheme.eq_ignore_ascii_case("Bearer") {
+        return Some(credentials.trim().to_string());
+    }
+    if scheme.eq_ignore_ascii_case("Basic") {
+        // The username is ignored, the app id is already in the path
+        let decoded = String::from_utf8(base64::decode(credentials.trim()).ok()?).ok()?;
+        return decoded.split_once(':').map(|(_, password)| password.to_string());
+    }
+    None
+}
+
+/// Queue a message to be posted in the apps thread by the bot
+async fn send_message(state: &SendEmbed, destination: Destination, message: MessageData) {
+    let lock = state.write().await;
+    lock.send((destination, message, None))
+        .await
+        .expect("Failed to send embed");
+    drop(lock);
+}
+
+/// Queue a message and wait for the bot to post it
+///
+/// Gives up with a gateway timeout after [`WAIT_TIMEOUT`], since the bot may not be
+/// connected or may be busy with earlier deliveries
+async fn send_message_and_wait(
+    state: &SendEmbed,
+    destination: Destination,
+    message: MessageData,
+) -> Result<Message, StatusCode> {
+    let (reply, posted) = oneshot::channel();
+    let lock = state.write().await;
+    lock.send((destination, message, Some(reply)))
+        .await
+        .expect("Failed to send embed");
+    drop(lock);
+    match tokio::time::timeout(WAIT_TIMEOUT, posted).await {
+        Ok(Ok(Some(posted))) => Ok(posted),
+        Ok(_) => Err(StatusCode::BAD_GATEWAY),
+        Err(_) => Err(StatusCode::GATEWAY_TIMEOUT),
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
 }
 
 async fn url_encode(input: &str) -> String {
@@ -198,3 +710,69 @@ async fn url_encode(input: &str) -> String {
         .replace('}', "%7d")
         .replace('~', "%7e")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn app(fields: serde_json::Value) -> AppCollection {
+        let mut app = serde_json::json!({
+            "_id": {"$oid": "62a1f1b2c3d4e5f6a7b8c9d0"},
+            "app_id": 5,
+            "app_name": "app",
+            "owner": {"reference": "user", "id": {"$oid": "62a1f1b2c3d4e5f6a7b8c9d1"}},
+            "server_id": 1,
+            "channel_id": 1,
+            "approved": true,
+        });
+        app.as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        serde_json::from_value(app).unwrap()
+    }
+
+    #[test]
+    fn header_tokens() {
+        let token = |pairs: &[(&'static str, &str)]| header_token(&headers(pairs));
+        assert_eq!(token(&[("Authorization", "Bearer abc")]).as_deref(), Some("abc"));
+        assert_eq!(token(&[("Authorization", "bearer abc")]).as_deref(), Some("abc"));
+        assert_eq!(token(&[("X-HookMe-Token", " abc ")]).as_deref(), Some("abc"));
+        // "user:secret", the username is ignored
+        let basic = [("Authorization", "Basic dXNlcjpzZWNyZXQ=")];
+        assert_eq!(token(&basic).as_deref(), Some("secret"));
+        let both = [("X-HookMe-Token", "abc"), ("Authorization", "Bearer other")];
+        assert_eq!(token(&both).as_deref(), Some("abc"));
+        assert_eq!(token(&[("Authorization", "Basic not base64")]), None);
+        assert_eq!(token(&[("Authorization", "Digest abc")]), None);
+        assert_eq!(token(&[]), None);
+    }
+
+    #[test]
+    fn verify_signature_needs_a_secret() {
+        let scheme =
+            serde_json::json!({"header": "X-Sig", "algorithm": "sha256", "encoding": "hex"});
+        let sign = |secret: &str| {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+            mac.update(b"body");
+            headers(&[("X-Sig", &hex::encode(mac.finalize().into_bytes()))])
+        };
+        // Signed with an empty key, which a missing secret must not fall back to
+        let unset = app(serde_json::json!({"signature": scheme}));
+        assert_eq!(verify_signature(&unset, &sign(""), b"body"), Some(false));
+        let set = app(serde_json::json!({"signature": scheme, "secret": "s"}));
+        assert_eq!(verify_signature(&set, &sign("s"), b"body"), Some(true));
+        let unsigned = app(serde_json::json!({"secret": "s"}));
+        assert_eq!(verify_signature(&unsigned, &sign("s"), b"body"), None);
+    }
+}
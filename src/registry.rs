@@ -0,0 +1,181 @@
+use crate::body_type::{non_empty, Embed, EmbedAuthor, EmbedData, EmbedField};
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+
+const BLUE: u32 = 0x2496ed;
+const RED: u32 = 0xd73a49;
+
+/// The most manifests listed in one embed before the rest are summarised
+const MAX_MANIFESTS: usize = 15;
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    #[serde(default)]
+    media_type: String,
+    #[serde(default)]
+    digest: String,
+    repository: String,
+    #[serde(default)]
+    tag: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Actor {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Request {
+    #[serde(default)]
+    host: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Event {
+    #[serde(default)]
+    timestamp: String,
+    action: String,
+    target: Target,
+    #[serde(default)]
+    actor: Actor,
+    #[serde(default)]
+    request: Request,
+}
+
+impl Event {
+    /// Blob uploads and pulls are noise, only tagged pushes and deletes are worth posting
+    ///
+    /// The registry sends every event in its own request, so a multi-arch push arrives as one
+    /// untagged manifest per platform followed by the tagged index, only the index is posted
+    fn is_interesting(&self) -> bool {
+        match self.action.as_str() {
+            "push" => self.target.tag.is_some(),
+            "delete" => true,
+            _ => false,
+        }
+    }
+}
+
+/// The notification envelope sent by Docker Distribution and compatible registries
+#[derive(Deserialize, Debug)]
+pub struct RegistryNotification {
+    events: Vec<Event>,
+}
+
+impl RegistryNotification {
+    /// Group the interesting events so one push of many manifests gives a single embed
+    fn pushes(&self) -> Vec<Vec<&Event>> {
+        let mut pushes: Vec<Vec<&Event>> = vec![];
+        for event in self.events.iter().filter(|event| event.is_interesting()) {
+            let same_push = |push: &&mut Vec<&Event>| {
+                push[0].action == event.action
+                    && push[0].target.repository == event.target.repository
+                    && push[0].actor.name == event.actor.name
+            };
+            match pushes.iter_mut().find(same_push) {
+                Some(push) => push.push(event),
+                None => pushes.push(vec![event]),
+            }
+        }
+        pushes
+    }
+}
+
+fn render_push(push: &[&Event]) -> EmbedData {
+    let first = push[0];
+    let repository = match non_empty(&first.request.host) {
+        Some(host) => format!("{host}/{}", first.target.repository),
+        None => first.target.repository.clone(),
+    };
+    let mut tags: Vec<&str> = push
+        .iter()
+        .filter_map(|event| event.target.tag.as_deref())
+        .collect();
+    tags.sort_unstable();
+    tags.dedup();
+    let (verb, color) = if first.action == "delete" {
+        ("Deleted", RED)
+    } else {
+        ("Pushed", BLUE)
+    };
+    let title = if tags.is_empty() {
+        match push.len() {
+            1 => format!("[{repository}] {verb} 1 manifest"),
+            count => format!("[{repository}] {verb} {count} manifests"),
+        }
+    } else {
+        format!("[{repository}] {verb} {}", tags.join(", "))
+    };
+    let mut description = push
+        .iter()
+        .take(MAX_MANIFESTS)
+        .map(|event| {
+            let digest = event.target.digest.trim_start_matches("sha256:");
+            let mut line = format!("`{}`", digest.chars().take(12).collect::<String>());
+            if let Some(tag) = &event.target.tag {
+                line.push_str(&format!(" {tag}"));
+            }
+            let media_type = &event.target.media_type;
+            if media_type.contains("list") || media_type.contains("index") {
+                line.push_str(" (index)");
+            }
+            line
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    if push.len() > MAX_MANIFESTS {
+        description.push_str(&format!("\n...and {} more", push.len() - MAX_MANIFESTS));
+    }
+    let mut embed = EmbedData::new(
+        &title,
+        &description,
+        "",
+        color,
+        "Registry",
+        EmbedAuthor::new(&first.actor.name, "", ""),
+        vec![EmbedField::new("Manifests", &push.len().to_string(), true)],
+    );
+    embed.timestamp = non_empty(&first.timestamp);
+    embed
+}
+
+impl Embed for RegistryNotification {
+    fn get_username(&self) -> String {
+        self.events
+            .first()
+            .map(|event| event.actor.name.clone())
+            .unwrap_or_default()
+    }
+
+    fn get_avatar_url(&self) -> String { "".into() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> {
+        self.pushes().iter().map(|push| render_push(push)).collect()
+    }
+}
+
+/// Container registry notifications, authenticated with the app token
+pub struct Registry;
+
+impl Source for Registry {
+    fn name(&self) -> &'static str { "registry" }
+
+    fn detect(&self, request: &Incoming) -> bool {
+        request
+            .header("Content-Type")
+            .map(|kind| kind.contains("vnd.docker.distribution.events"))
+            .unwrap_or(false)
+            || serde_json::from_slice::<RegistryNotification>(request.body).is_ok()
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let notification: RegistryNotification = serde_json::from_slice(request.body)?;
+        if notification.pushes().is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(notification)))
+    }
+}
@@ -0,0 +1,300 @@
+use crate::body_type::{non_empty, summary, Embed, EmbedAuthor, EmbedData, EmbedField};
+use crate::body_type::MAX_FIELDS;
+use crate::signature;
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+use serde_json::Value;
+
+const FATAL: u32 = 0x8b1a10;
+const ERROR: u32 = 0xe03e2f;
+const WARNING: u32 = 0xf5b000;
+const INFO: u32 = 0x2788ce;
+const DEBUG: u32 = 0x8d8d8d;
+const GREEN: u32 = 0x2ba185;
+
+/// The most frames shown for each exception, counted from where it was raised
+const MAX_FRAMES: usize = 5;
+
+#[derive(Deserialize, Debug)]
+pub struct Project {
+    name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Issue {
+    short_id: String,
+    title: String,
+    #[serde(default)]
+    culprit: String,
+    #[serde(default)]
+    level: String,
+    #[serde(default)]
+    status: String,
+    /// Sentry sends the counts as strings
+    #[serde(default)]
+    count: Value,
+    #[serde(default)]
+    user_count: Value,
+    project: Project,
+    #[serde(rename = "web_url", default)]
+    web_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Frame {
+    #[serde(default)]
+    filename: Option<String>,
+    #[serde(default)]
+    function: Option<String>,
+    #[serde(default)]
+    lineno: Option<u64>,
+    #[serde(default)]
+    in_app: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Stacktrace {
+    #[serde(default)]
+    frames: Vec<Frame>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Exception {
+    #[serde(rename = "type", default)]
+    kind: String,
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    stacktrace: Option<Stacktrace>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Exceptions {
+    #[serde(default)]
+    values: Vec<Exception>,
+}
+
+/// A single error event, as sent for error and issue alert webhooks
+#[derive(Deserialize, Debug)]
+pub struct ErrorEvent {
+    title: String,
+    #[serde(default)]
+    culprit: String,
+    #[serde(default)]
+    level: String,
+    #[serde(default)]
+    web_url: String,
+    #[serde(default)]
+    environment: Option<String>,
+    #[serde(default)]
+    exception: Option<Exceptions>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssueData {
+    issue: Issue,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct IssueHook {
+    action: String,
+    data: IssueData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EventAlertData {
+    event: ErrorEvent,
+    triggered_rule: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EventAlertHook {
+    data: EventAlertData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ErrorData {
+    error: ErrorEvent,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ErrorHook {
+    data: ErrorData,
+}
+
+/// A Sentry integration webhook, picked using the `Sentry-Hook-Resource` header
+#[derive(Debug)]
+pub enum SentryEvent {
+    Issue(IssueHook),
+    EventAlert(EventAlertHook),
+    Error(ErrorHook),
+}
+
+impl SentryEvent {
+    /// Parse the body for the given resource, resources we don't render give `Ok(None)`
+    pub fn parse(resource: &str, body: &[u8]) -> Result<Option<SentryEvent>, serde_json::Error> {
+        Ok(Some(match resource {
+            "issue" => SentryEvent::Issue(serde_json::from_slice(body)?),
+            "event_alert" => SentryEvent::EventAlert(serde_json::from_slice(body)?),
+            "error" => SentryEvent::Error(serde_json::from_slice(body)?),
+            _ => return Ok(None),
+        }))
+    }
+
+    fn render(&self) -> EmbedData {
+        match self {
+            SentryEvent::Issue(hook) => render_issue(hook),
+            SentryEvent::EventAlert(hook) => {
+                render_event(&hook.data.event, &format!("Alert: {}", hook.data.triggered_rule))
+            }
+            SentryEvent::Error(hook) => render_event(&hook.data.error, "Error"),
+        }
+    }
+}
+
+impl Embed for SentryEvent {
+    fn get_username(&self) -> String { "Sentry".into() }
+
+    fn get_avatar_url(&self) -> String { "".into() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { vec![self.render()] }
+}
+
+fn color(level: &str) -> u32 {
+    match level {
+        "fatal" => FATAL,
+        "error" => ERROR,
+        "warning" => WARNING,
+        "info" => INFO,
+        _ => DEBUG,
+    }
+}
+
+/// Sentry sends some numbers as strings, so show whichever we get
+fn count(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        Value::Null => "".into(),
+        other => other.to_string(),
+    }
+}
+
+fn render_issue(hook: &IssueHook) -> EmbedData {
+    let issue = &hook.data.issue;
+    let fields = [
+        ("Level", issue.level.clone()),
+        ("Status", issue.status.clone()),
+        ("Events", count(&issue.count)),
+        ("Users", count(&issue.user_count)),
+    ]
+    .iter()
+    .filter(|(_, value)| !value.is_empty())
+    .map(|(name, value)| EmbedField::new(name, value, true))
+    .collect();
+    EmbedData::new(
+        &format!(
+            "[{}] Issue {}: {} {}",
+            issue.project.name, hook.action, issue.short_id, issue.title
+        ),
+        &issue.culprit,
+        &issue.web_url,
+        if hook.action == "resolved" { GREEN } else { color(&issue.level) },
+        "Sentry",
+        EmbedAuthor::new(&issue.project.name, "", ""),
+        fields,
+    )
+}
+
+fn render_event(event: &ErrorEvent, kind: &str) -> EmbedData {
+    let mut fields: Vec<EmbedField> = vec![];
+    if let Some(environment) = event.environment.as_deref().and_then(non_empty) {
+        fields.push(EmbedField::new("Environment", &environment, true));
+    }
+    fields.push(EmbedField::new("Level", &event.level, true));
+    let exceptions = event
+        .exception
+        .as_ref()
+        .map(|exception| exception.values.as_slice())
+        .unwrap_or_default();
+    // Sentry lists the innermost exception last, which is usually the interesting one
+    for exception in exceptions.iter().rev() {
+        fields.push(EmbedField::new(
+            &format!("{}: {}", exception.kind, exception.value)
+                .chars()
+                .take(256)
+                .collect::<String>(),
+            &frame_summary(exception),
+            false,
+        ));
+    }
+    EmbedData::new(
+        &format!("{kind}: {}", event.title),
+        &event.culprit,
+        &event.web_url,
+        color(&event.level),
+        "Sentry",
+        EmbedAuthor::new("Sentry", "", ""),
+        fields
+            .into_iter()
+            .filter(|field| !field.value.is_empty())
+            .take(MAX_FIELDS)
+            .collect(),
+    )
+}
+
+/// The frames closest to where an exception was raised, preferring the apps own code
+fn frame_summary(exception: &Exception) -> String {
+    let frames = exception
+        .stacktrace
+        .as_ref()
+        .map(|stacktrace| stacktrace.frames.as_slice())
+        .unwrap_or_default();
+    let in_app = frames.iter().any(|frame| frame.in_app);
+    let lines = frames
+        .iter()
+        .rev()
+        .filter(|frame| frame.in_app || !in_app)
+        .take(MAX_FRAMES)
+        .map(|frame| {
+            let mut line = format!(
+                "`{}` in {}",
+                frame.function.as_deref().unwrap_or("?"),
+                frame.filename.as_deref().unwrap_or("?")
+            );
+            if let Some(lineno) = frame.lineno {
+                line.push_str(&format!(":{lineno}"));
+            }
+            line
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    summary(Some(&lines))
+}
+
+/// Sentry integration webhooks, authenticated with the `Sentry-Hook-Signature` header
+///
+/// The signature uses the integration's client secret, saved with `secret <app id> <secret>`
+pub struct Sentry;
+
+impl Source for Sentry {
+    fn name(&self) -> &'static str { "sentry" }
+
+    fn detect(&self, request: &Incoming) -> bool {
+        request.header("Sentry-Hook-Resource").is_some()
+    }
+
+    fn authenticate(&self, app: &AppCollection, request: &Incoming) -> Option<bool> {
+        Some(match (&app.secret, request.header("Sentry-Hook-Signature")) {
+            (Some(secret), Some(sig)) => signature::verify_sha256_hex(secret, request.body, sig),
+            _ => false,
+        })
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let resource = request.header("Sentry-Hook-Resource").unwrap_or_default();
+        let event = SentryEvent::parse(resource, request.body)?;
+        Ok(event.map(|e| Box::new(e) as Box<dyn Embed + Send>))
+    }
+}
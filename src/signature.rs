@@ -0,0 +1,268 @@
+use axum::http::HeaderMap;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+
+type HmacSha256 = Hmac<Sha256>;
+type HmacSha1 = Hmac<Sha1>;
+
+/// How far a signed timestamp may be from now by default, in seconds
+const DEFAULT_TOLERANCE: u64 = 5 * 60;
+
+/// Check a hex encoded HMAC-SHA256 signature of `body` against `secret`
+///
+/// The comparison is done in constant time by the hmac crate
+pub fn verify_sha256_hex(secret: &str, body: &[u8], signature: &str) -> bool {
+    let signature = match hex::decode(signature.trim()) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take a key of any size");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Compare a plain shared token such as GitLab's `X-Gitlab-Token` in constant time
+pub fn verify_token(secret: &str, token: &str) -> bool {
+    secret.as_bytes().ct_eq(token.as_bytes()).into()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    Hex,
+    Base64,
+}
+
+/// A timestamp signed along with the body, which stops old requests being replayed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedTimestamp {
+    /// The header holding the timestamp, when unset it's read from a `t=` pair in the
+    /// signature header like Stripe sends
+    #[serde(default)]
+    pub header: Option<String>,
+    /// What is signed before the body, with `{timestamp}` replaced, `v0:{timestamp}:` for
+    /// Slack or `{timestamp}.` for Stripe
+    pub prefix: String,
+    /// How many seconds the timestamp may be from now
+    #[serde(default = "default_tolerance")]
+    pub tolerance: u64,
+}
+
+fn default_tolerance() -> u64 { DEFAULT_TOLERANCE }
+
+/// How an app signs its requests with its secret
+///
+/// The signature header may hold several comma separated values, like Stripe's
+/// `t=...,v1=...`, any of those starting with `prefix` is accepted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignatureScheme {
+    pub header: String,
+    pub algorithm: Algorithm,
+    pub encoding: Encoding,
+    /// Stripped from the signature before decoding, like GitHub's `sha256=`
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub timestamp: Option<SignedTimestamp>,
+}
+
+impl SignatureScheme {
+    /// The schemes of well known senders, so they don't have to be written out by hand
+    pub fn preset(name: &str) -> Option<SignatureScheme> {
+        let scheme = match name {
+            "github" => SignatureScheme {
+                header: "X-Hub-Signature-256".into(),
+                algorithm: Algorithm::Sha256,
+                encoding: Encoding::Hex,
+                prefix: "sha256=".into(),
+                timestamp: None,
+            },
+            "slack" => SignatureScheme {
+                header: "X-Slack-Signature".into(),
+                algorithm: Algorithm::Sha256,
+                encoding: Encoding::Hex,
+                prefix: "v0=".into(),
+                timestamp: Some(SignedTimestamp {
+                    header: Some("X-Slack-Request-Timestamp".into()),
+                    prefix: "v0:{timestamp}:".into(),
+                    tolerance: DEFAULT_TOLERANCE,
+                }),
+            },
+            "stripe" => SignatureScheme {
+                header: "Stripe-Signature".into(),
+                algorithm: Algorithm::Sha256,
+                encoding: Encoding::Hex,
+                prefix: "v1=".into(),
+                timestamp: Some(SignedTimestamp {
+                    header: None,
+                    prefix: "{timestamp}.".into(),
+                    tolerance: DEFAULT_TOLERANCE,
+                }),
+            },
+            _ => return None,
+        };
+        Some(scheme)
+    }
+
+    /// Check the signature of a request before anything else looks at its body
+    pub fn verify(&self, secret: &str, headers: &HeaderMap, body: &[u8]) -> bool {
+        let value = match headers.get(&self.header).and_then(|value| value.to_str().ok()) {
+            Some(value) => value,
+            None => return false,
+        };
+        let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+        let mut signed = vec![];
+        if let Some(timestamp) = &self.timestamp {
+            let sent = match &timestamp.header {
+                Some(header) => headers.get(header).and_then(|value| value.to_str().ok()),
+                None => parts.iter().find_map(|part| part.strip_prefix("t=")),
+            };
+            let sent = match sent.and_then(|sent| sent.trim().parse::<u64>().ok()) {
+                Some(sent) => sent,
+                None => return false,
+            };
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or_default();
+            if now.abs_diff(sent) > timestamp.tolerance {
+                return false;
+            }
+            signed.extend_from_slice(
+                timestamp
+                    .prefix
+                    .replace("{timestamp}", &sent.to_string())
+                    .as_bytes(),
+            );
+        }
+        signed.extend_from_slice(body);
+        parts
+            .iter()
+            .filter_map(|part| part.strip_prefix(self.prefix.as_str()))
+            .filter_map(|signature| match self.encoding {
+                Encoding::Hex => hex::decode(signature).ok(),
+                Encoding::Base64 => base64::decode(signature).ok(),
+            })
+            .any(|signature| self.verify_mac(secret, &signed, &signature))
+    }
+
+    fn verify_mac(&self, secret: &str, signed: &[u8], signature: &[u8]) -> bool {
+        let key = secret.as_bytes();
+        match self.algorithm {
+            Algorithm::Sha1 => {
+                let mut mac =
+                    HmacSha1::new_from_slice(key).expect("HMAC can take a key of any size");
+                mac.update(signed);
+                mac.verify_slice(signature).is_ok()
+            }
+            Algorithm::Sha256 => {
+                let mut mac =
+                    HmacSha256::new_from_slice(key).expect("HMAC can take a key of any size");
+                mac.update(signed);
+                mac.verify_slice(signature).is_ok()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn sign(secret: &str, signed: &str) -> Vec<u8> {
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(signed.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// The example from GitHub's documentation on validating webhook deliveries
+    #[test]
+    fn github_preset() {
+        let scheme = SignatureScheme::preset("github").unwrap();
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        let signed = headers(&[("X-Hub-Signature-256", signature)]);
+        assert!(scheme.verify("It's a Secret to Everybody", &signed, b"Hello, World!"));
+        assert!(!scheme.verify("It's a Secret to Everybody", &signed, b"Hello, World?"));
+        assert!(!scheme.verify("Another secret", &signed, b"Hello, World!"));
+        assert!(!scheme.verify("It's a Secret to Everybody", &HeaderMap::new(), b"Hello, World!"));
+    }
+
+    /// The example from Slack's documentation on verifying requests
+    #[test]
+    fn slack_preset() {
+        let secret = "8f742231b10e8888abcd99yyyzzz85a5";
+        let body = "token=xyzz0WbapA4vBCDEFasx0q6G&team_id=T1DC2JH3J&team_domain=testteamnow&\
+            channel_id=G8PSS9T3V&channel_name=foobar&user_id=U2CERLKJA&user_name=roadrunner&\
+            command=%2Fwebhook-collect&text=&\
+            response_url=https%3A%2F%2Fhooks.slack.com%2Fcommands%2FT1DC2JH3J%2F397700885554%2F\
+            96rGlfmibIGlgcZRskXaIFfN&\
+            trigger_id=398738663015.47445629121.803a0bc887a14d10d2c447fce8b6703c";
+        let signed = headers(&[
+            ("X-Slack-Request-Timestamp", "1531420618"),
+            (
+                "X-Slack-Signature",
+                "v0=a2114d57b48eac39b9ad189dd8316235a7b4a8d21a10bd27519666489c69b503",
+            ),
+        ]);
+        let mut scheme = SignatureScheme::preset("slack").unwrap();
+        // The documented timestamp is long past, so it's only accepted with no tolerance
+        assert!(!scheme.verify(secret, &signed, body.as_bytes()));
+        scheme.timestamp.as_mut().unwrap().tolerance = u64::MAX;
+        assert!(scheme.verify(secret, &signed, body.as_bytes()));
+        assert!(!scheme.verify(secret, &signed, b"token=changed"));
+    }
+
+    #[test]
+    fn stripe_preset() {
+        let scheme = SignatureScheme::preset("stripe").unwrap();
+        let body = r#"{"id":"evt_1","type":"charge.succeeded"}"#;
+        let now = now();
+        let signature = hex::encode(sign("whsec_test", &format!("{now}.{body}")));
+        let value = format!("t={now},v1=bad,v1={signature}");
+        let signed = headers(&[("Stripe-Signature", &value)]);
+        assert!(scheme.verify("whsec_test", &signed, body.as_bytes()));
+        assert!(!scheme.verify("whsec_other", &signed, body.as_bytes()));
+
+        let stale = now - DEFAULT_TOLERANCE - 60;
+        let signature = hex::encode(sign("whsec_test", &format!("{stale}.{body}")));
+        let value = format!("t={stale},v1={signature}");
+        let signed = headers(&[("Stripe-Signature", &value)]);
+        assert!(!scheme.verify("whsec_test", &signed, body.as_bytes()));
+    }
+
+    #[test]
+    fn base64_encoding() {
+        let scheme: SignatureScheme =
+            serde_json::from_str(r#"{"header":"X-Sig","algorithm":"sha256","encoding":"base64"}"#)
+                .unwrap();
+        let signature = base64::encode(sign("secret", "body"));
+        assert!(scheme.verify("secret", &headers(&[("X-Sig", &signature)]), b"body"));
+        let hex = hex::encode(sign("secret", "body"));
+        assert!(!scheme.verify("secret", &headers(&[("X-Sig", &hex)]), b"body"));
+    }
+}
@@ -0,0 +1,411 @@
+use crate::body_type::{non_empty, Embed, EmbedAuthor, EmbedData, EmbedField, EmbedFooter};
+use crate::body_type::{EmbedMedia, MessageData, MAX_EMBEDS};
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::Deserialize;
+use serenity::model::Timestamp;
+
+/// Slack's named attachment colors
+const GOOD: u32 = 0x2eb886;
+const WARNING: u32 = 0xdaa038;
+const DANGER: u32 = 0xa30200;
+
+#[derive(Deserialize, Debug)]
+pub struct AttachmentField {
+    title: String,
+    value: String,
+    #[serde(default)]
+    short: bool,
+}
+
+/// A legacy Slack message attachment
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SlackAttachment {
+    fallback: Option<String>,
+    color: Option<String>,
+    pretext: Option<String>,
+    author_name: Option<String>,
+    author_link: Option<String>,
+    author_icon: Option<String>,
+    title: Option<String>,
+    title_link: Option<String>,
+    text: Option<String>,
+    fields: Vec<AttachmentField>,
+    image_url: Option<String>,
+    thumb_url: Option<String>,
+    footer: Option<String>,
+    footer_icon: Option<String>,
+    ts: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TextObject {
+    text: String,
+}
+
+/// The Block Kit blocks that have a sensible embed equivalent, anything else is skipped
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Block {
+    Header {
+        text: TextObject,
+    },
+    Section {
+        text: Option<TextObject>,
+        #[serde(default)]
+        fields: Vec<TextObject>,
+    },
+    Context {
+        #[serde(default)]
+        elements: Vec<serde_json::Value>,
+    },
+    Image {
+        image_url: String,
+    },
+    Divider,
+    #[serde(other)]
+    Unsupported,
+}
+
+/// Slack's incoming webhook payload
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct SlackWebhook {
+    text: Option<String>,
+    username: Option<String>,
+    icon_url: Option<String>,
+    attachments: Vec<SlackAttachment>,
+    blocks: Vec<Block>,
+}
+
+impl SlackWebhook {
+    fn render_blocks(&self) -> Option<EmbedData> {
+        if self.blocks.is_empty() {
+            return None;
+        }
+        let mut embed = EmbedData::default();
+        let mut description = vec![];
+        let mut fields = vec![];
+        let mut context = vec![];
+        for block in &self.blocks {
+            match block {
+                Block::Header { text } if embed.title.is_none() => {
+                    embed.title = Some(mrkdwn_to_markdown(&text.text));
+                }
+                Block::Header { text } => description.push(format!("**{}**", text.text)),
+                Block::Section {
+                    text,
+                    fields: section_fields,
+                } => {
+                    if let Some(text) = text {
+                        description.push(mrkdwn_to_markdown(&text.text));
+                    }
+                    for field in section_fields {
+                        let value = mrkdwn_to_markdown(&field.text);
+                        // Block Kit fields have no title, so use a zero width space
+                        fields.push(EmbedField::new("\u{200b}", &value, true));
+                    }
+                }
+                Block::Context { elements } => {
+                    context.extend(
+                        elements
+                            .iter()
+                            .filter_map(|element| element.get("text")?.as_str())
+                            .map(mrkdwn_to_markdown),
+                    );
+                }
+                Block::Image { image_url } if embed.image.is_none() => {
+                    embed.image = Some(EmbedMedia::new(image_url));
+                }
+                Block::Divider => description.push("".into()),
+                _ => {}
+            }
+        }
+        embed.description = non_empty(&description.join("\n"));
+        embed.fields = if fields.is_empty() { None } else { Some(fields) };
+        embed.footer = non_empty(&context.join(" | ")).map(|text| EmbedFooter {
+            text,
+            icon_url: None,
+        });
+        Some(embed)
+    }
+}
+
+impl SlackAttachment {
+    fn render(&self) -> EmbedData {
+        let mut description = vec![];
+        if let Some(pretext) = &self.pretext {
+            description.push(mrkdwn_to_markdown(pretext));
+        }
+        match (&self.text, &self.title, &self.fallback) {
+            (Some(text), _, _) => description.push(mrkdwn_to_markdown(text)),
+            (None, None, Some(fallback)) => description.push(mrkdwn_to_markdown(fallback)),
+            _ => {}
+        }
+        let fields: Vec<EmbedField> = self
+            .fields
+            .iter()
+            .map(|field| {
+                EmbedField::new(
+                    &field.title,
+                    &mrkdwn_to_markdown(&field.value),
+                    field.short,
+                )
+            })
+            .collect();
+        EmbedData {
+            title: self.title.as_deref().map(mrkdwn_to_markdown),
+            description: non_empty(&description.join("\n")),
+            url: self.title_link.clone(),
+            timestamp: self.ts.as_ref().and_then(timestamp),
+            color: self.color.as_deref().and_then(color),
+            footer: self.footer.as_ref().map(|footer| EmbedFooter {
+                text: mrkdwn_to_markdown(footer),
+                icon_url: self.footer_icon.clone(),
+            }),
+            image: self.image_url.as_deref().map(EmbedMedia::new),
+            thumbnail: self.thumb_url.as_deref().map(EmbedMedia::new),
+            author: self.author_name.as_ref().map(|name| EmbedAuthor {
+                name: name.clone(),
+                url: self.author_link.clone(),
+                icon_url: self.author_icon.clone(),
+            }),
+            fields: if fields.is_empty() { None } else { Some(fields) },
+            ..Default::default()
+        }
+    }
+}
+
+impl Embed for SlackWebhook {
+    fn get_username(&self) -> String { self.username.clone().unwrap_or_default() }
+
+    fn get_avatar_url(&self) -> String { self.icon_url.clone().unwrap_or_default() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> {
+        self.render_blocks()
+            .into_iter()
+            .chain(self.attachments.iter().map(SlackAttachment::render))
+            .take(MAX_EMBEDS)
+            .collect()
+    }
+
+    fn get_message(&self) -> MessageData {
+        // With blocks the text is only a notification fallback
+        let content = match &self.text {
+            Some(text) if self.blocks.is_empty() => mrkdwn_to_markdown(text),
+            _ => "".into(),
+        };
+        MessageData {
+            content,
+            ..MessageData::from_embeds(self.get_embeds())
+        }
+    }
+}
+
+/// Slack incoming webhooks, authenticated with the app token
+pub struct Slack;
+
+impl Source for Slack {
+    fn name(&self) -> &'static str { "slack" }
+
+    fn detect(&self, request: &Incoming) -> bool {
+        match serde_json::from_slice::<serde_json::Value>(request.body) {
+            Ok(serde_json::Value::Object(body)) => {
+                !body.contains_key("content")
+                    && !body.contains_key("embeds")
+                    && ["text", "attachments", "blocks"]
+                        .iter()
+                        .any(|key| body.contains_key(*key))
+            }
+            _ => false,
+        }
+    }
+
+    fn parse(&self, _app: &AppCollection, request: &Incoming) -> Parsed {
+        let webhook: SlackWebhook = serde_json::from_slice(request.body)?;
+        Ok(Some(Box::new(webhook)))
+    }
+}
+
+/// Convert Slack's mrkdwn to Discord markdown
+///
+/// Links like `<url|label>` become `[label](url)`, `*bold*` and `~strike~` spans get doubled
+/// and the html entities Slack requires are decoded, code spans are left alone
+pub fn mrkdwn_to_markdown(input: &str) -> String {
+    let chars: Vec<char> = input.chars().collect();
+    let spans = emphasis(&chars);
+    let mut output = String::with_capacity(input.len());
+    let mut in_code = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '`' => {
+                in_code = !in_code;
+                output.push(c);
+            }
+            '*' | '~' if spans.contains(&i) => {
+                output.push(c);
+                output.push(c);
+            }
+            '<' if !in_code => match link_end(&chars, i) {
+                Some(end) => {
+                    let inner: String = chars[i + 1..end].iter().collect();
+                    output.push_str(&convert_link(&inner));
+                    i = end;
+                }
+                None => output.push(c),
+            },
+            _ => output.push(c),
+        }
+        i += 1;
+    }
+    output
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// The position of the `>` closing a `<` at `start`
+fn link_end(chars: &[char], start: usize) -> Option<usize> {
+    chars[start + 1..]
+        .iter()
+        .position(|&c| c == '>')
+        .map(|end| start + 1 + end)
+}
+
+/// Find the `*` and `~` that open or close a span on one line
+///
+/// Like Slack, a span has to start at the beginning of a word and end at the end of one, so
+/// markers inside URLs or arithmetic like `2*3*4` are left alone, as are those in code and links
+fn emphasis(chars: &[char]) -> Vec<usize> {
+    let mut found = vec![];
+    let mut bold = None;
+    let mut strike = None;
+    let mut in_code = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '`' => in_code = !in_code,
+            '\n' => {
+                bold = None;
+                strike = None;
+            }
+            '<' if !in_code => {
+                if let Some(end) = link_end(chars, i) {
+                    i = end;
+                }
+            }
+            '*' | '~' if !in_code => {
+                let open = if c == '*' { &mut bold } else { &mut strike };
+                let before = i.checked_sub(1).map(|before| chars[before]);
+                let after = chars.get(i + 1).copied();
+                let closes = before.is_some_and(|before| !before.is_whitespace())
+                    && after.is_none_or(|after| !after.is_alphanumeric());
+                let opens = before.is_none_or(|before| !before.is_alphanumeric())
+                    && after.is_some_and(|after| !after.is_whitespace());
+                match *open {
+                    Some(start) if closes && start + 1 < i => {
+                        found.extend([start, i]);
+                        *open = None;
+                    }
+                    _ if opens => *open = Some(i),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    found
+}
+
+/// Convert the inside of a `<...>` mrkdwn link, mention or channel reference
+fn convert_link(inner: &str) -> String {
+    let (target, label) = match inner.split_once('|') {
+        Some((target, label)) => (target, Some(label)),
+        None => (inner, None),
+    };
+    match (target.chars().next(), label) {
+        (Some('!'), _) => label.unwrap_or(&target[1..]).to_string(),
+        (Some('@'), Some(label)) | (Some('#'), Some(label)) => {
+            format!("{}{label}", &target[..1])
+        }
+        (Some('@'), None) | (Some('#'), None) => target.to_string(),
+        (_, Some(label)) => format!("[{label}]({target})"),
+        (_, None) => target.to_string(),
+    }
+}
+
+fn color(color: &str) -> Option<u32> {
+    match color {
+        "good" => Some(GOOD),
+        "warning" => Some(WARNING),
+        "danger" => Some(DANGER),
+        hex => u32::from_str_radix(hex.trim_start_matches('#'), 16).ok(),
+    }
+}
+
+/// Slack timestamps are unix seconds, sent either as a number or a string
+fn timestamp(ts: &serde_json::Value) -> Option<String> {
+    let secs = match ts {
+        serde_json::Value::Number(number) => number.as_f64()?,
+        serde_json::Value::String(string) => string.parse().ok()?,
+        _ => return None,
+    };
+    Timestamp::from_unix_timestamp(secs as i64)
+        .ok()
+        .map(|timestamp| timestamp.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mrkdwn_to_markdown;
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            mrkdwn_to_markdown("<https://example.com|the docs>"),
+            "[the docs](https://example.com)"
+        );
+        assert_eq!(mrkdwn_to_markdown("see <https://example.com>"), "see https://example.com");
+        assert_eq!(mrkdwn_to_markdown("a < b"), "a < b");
+    }
+
+    #[test]
+    fn mentions() {
+        assert_eq!(mrkdwn_to_markdown("<@U024BE7LH>"), "@U024BE7LH");
+        assert_eq!(mrkdwn_to_markdown("<@U024BE7LH|bob>"), "@bob");
+        assert_eq!(mrkdwn_to_markdown("<#C024BE7LR|general>"), "#general");
+        assert_eq!(mrkdwn_to_markdown("<!here> deploy done"), "here deploy done");
+        assert_eq!(mrkdwn_to_markdown("<!channel>"), "channel");
+        assert_eq!(mrkdwn_to_markdown("<!subteam^SAZ94GDB8|@oncall>"), "@oncall");
+    }
+
+    #[test]
+    fn emphasis() {
+        assert_eq!(mrkdwn_to_markdown("*bold* and ~gone~"), "**bold** and ~~gone~~");
+        assert_eq!(mrkdwn_to_markdown("a *bold phrase*, then"), "a **bold phrase**, then");
+        assert_eq!(mrkdwn_to_markdown("*~both~*"), "**~~both~~**");
+        assert_eq!(mrkdwn_to_markdown("2*3*4 = 24"), "2*3*4 = 24");
+        assert_eq!(mrkdwn_to_markdown("a * b * c"), "a * b * c");
+        assert_eq!(mrkdwn_to_markdown("https://host/~user/~other"), "https://host/~user/~other");
+        assert_eq!(mrkdwn_to_markdown("*not\nbold*"), "*not\nbold*");
+        assert_eq!(mrkdwn_to_markdown("<https://host/~a|*b*>"), "[*b*](https://host/~a)");
+    }
+
+    #[test]
+    fn code_spans() {
+        assert_eq!(mrkdwn_to_markdown("run `rm *.tmp` now"), "run `rm *.tmp` now");
+        assert_eq!(mrkdwn_to_markdown("`*x*` and *y*"), "`*x*` and **y**");
+        assert_eq!(mrkdwn_to_markdown("```<@U1> ~a~```"), "```<@U1> ~a~```");
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(mrkdwn_to_markdown("1 &lt; 2 &amp;&amp; 3 &gt; 2"), "1 < 2 && 3 > 2");
+        assert_eq!(mrkdwn_to_markdown("&amp;lt;"), "&lt;");
+    }
+}
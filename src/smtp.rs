@@ -0,0 +1,400 @@
+use crate::attachment::{max_attachment_size, Attachment};
+use crate::body_type::{summary, Destination, EmbedAuthor, EmbedData, EmbedField, MessageData};
+use crate::{get_approved_app, send_message, AppCollection, SendEmbed};
+use crate::{heartbeat, token, UserCollection};
+use mail_parser::{MessageParser, MimeHeaders};
+use mongodb::Database;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::timeout;
+
+const GREY: u32 = 0x808080;
+
+/// Commands longer than this are rejected, RFC 5321 only requires 512 bytes
+const MAX_LINE: u64 = 1000;
+
+/// How long a client may take to send a command, the 5 minutes RFC 5321 suggests
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How long a client may take to send a whole message, RFC 5321 suggests 10 minutes
+const DATA_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// The most sessions open at once, more connections are turned away
+const MAX_SESSIONS: usize = 100;
+
+/// Accept mail for `<app_id>+<token>@<domain>` and post each one in the apps thread
+///
+/// There's no TLS or authentication beyond the token in the address, so the port should
+/// only be reachable by the systems that need it
+pub async fn listen(port: u16, state: SendEmbed, db: Database) {
+    let addr = SocketAddr::from((Ipv4Addr::new(0, 0, 0, 0), port));
+    let listener = TcpListener::bind(addr)
+        .await
+        .expect("Failed to bind SMTP listener");
+    println!("Listening for mail on {}", addr);
+    let sessions = Arc::new(Semaphore::new(MAX_SESSIONS));
+    loop {
+        match listener.accept().await {
+            Ok((mut stream, _)) => {
+                let permit = match sessions.clone().try_acquire_owned() {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        let _ = stream.write_all(b"421 Too many connections\r\n").await;
+                        continue;
+                    }
+                };
+                let state = state.clone();
+                let db = db.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = session(stream, &state, &db).await {
+                        eprintln!("SMTP session failed: {e}");
+                    }
+                    drop(permit);
+                });
+            }
+            Err(e) => eprintln!("Failed to accept SMTP connection: {e}"),
+        }
+    }
+}
+
+/// An app a message has been accepted for
+struct Recipient {
+    app: AppCollection,
+    user: UserCollection,
+}
+
+async fn session(stream: TcpStream, state: &SendEmbed, db: &Database) -> io::Result<()> {
+    let domain = std::env::var("SMTP_DOMAIN")
+        .ok()
+        .filter(|domain| !domain.trim().is_empty());
+    let hostname = domain.clone().unwrap_or_else(|| "hookme".into());
+    // base64 grows attachments by a third, and the text still needs some room
+    let max_size = max_attachment_size() * 2;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    reply(&mut writer, &format!("220 {hostname} HookMe ESMTP")).await?;
+    let mut has_sender = false;
+    let mut recipients: Vec<Recipient> = vec![];
+    let mut line = vec![];
+    loop {
+        let read = timeout(COMMAND_TIMEOUT, read_line(&mut reader, &mut line, MAX_LINE)).await;
+        let read = match read {
+            Ok(read) => read?,
+            Err(_) => return reply(&mut writer, &format!("421 {hostname} timed out")).await,
+        };
+        if read == 0 {
+            return Ok(());
+        }
+        let line = String::from_utf8_lossy(&line);
+        let line = line.trim_end();
+        let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+        match command.to_ascii_uppercase().as_str() {
+            "HELO" => reply(&mut writer, &format!("250 {hostname}")).await?,
+            "EHLO" => {
+                let extensions = format!("250-{hostname}\r\n250-8BITMIME\r\n250 SIZE {max_size}");
+                reply(&mut writer, &extensions).await?
+            }
+            "MAIL" => {
+                has_sender = true;
+                recipients.clear();
+                reply(&mut writer, "250 OK").await?
+            }
+            "RCPT" if !has_sender => reply(&mut writer, "503 Need MAIL first").await?,
+            "RCPT" => match recipient(db, argument, domain.as_deref()).await {
+                Some(recipient) => {
+                    recipients.push(recipient);
+                    reply(&mut writer, "250 OK").await?
+                }
+                None => reply(&mut writer, "550 No such app or wrong token").await?,
+            },
+            "DATA" if recipients.is_empty() => {
+                reply(&mut writer, "503 Need RCPT first").await?
+            }
+            "DATA" => {
+                reply(&mut writer, "354 End data with <CR><LF>.<CR><LF>").await?;
+                let data = match timeout(DATA_TIMEOUT, read_data(&mut reader, max_size)).await {
+                    Ok(data) => data?,
+                    Err(_) => return reply(&mut writer, &format!("421 {hostname} timed out")).await,
+                };
+                let data = match data {
+                    Some(data) => data,
+                    None => {
+                        reply(&mut writer, "552 Message too large").await?;
+                        recipients.clear();
+                        continue;
+                    }
+                };
+                match parse_mail(&data) {
+                    Some(message) => {
+                        for Recipient { app, user } in recipients.drain(..) {
+                            heartbeat::beat(state, db, &app, &user).await;
+                            let destination = Destination::new(
+                                &app.app_name,
+                                "",
+                                app.server_id,
+                                app.channel_id,
+                                user.id,
+                                app.app_id,
+                            );
+                            send_message(state, destination, message.clone()).await;
+                        }
+                        reply(&mut writer, "250 OK").await?
+                    }
+                    None => reply(&mut writer, "554 Could not parse message").await?,
+                }
+                has_sender = false;
+                recipients.clear();
+            }
+            "RSET" => {
+                has_sender = false;
+                recipients.clear();
+                reply(&mut writer, "250 OK").await?
+            }
+            "NOOP" => reply(&mut writer, "250 OK").await?,
+            "VRFY" => reply(&mut writer, "252 Cannot verify").await?,
+            "QUIT" => {
+                reply(&mut writer, &format!("221 {hostname} closing")).await?;
+                return Ok(());
+            }
+            _ => reply(&mut writer, "502 Command not implemented").await?,
+        }
+    }
+}
+
+async fn reply(writer: &mut OwnedWriteHalf, line: &str) -> io::Result<()> {
+    writer.write_all(format!("{line}\r\n").as_bytes()).await
+}
+
+/// Read one line into `line`, giving up on the connection if it's longer than `limit`
+async fn read_line(
+    reader: &mut BufReader<OwnedReadHalf>,
+    line: &mut Vec<u8>,
+    limit: u64,
+) -> io::Result<usize> {
+    line.clear();
+    let read = reader.take(limit).read_until(b'\n', line).await?;
+    if read as u64 == limit && !line.ends_with(b"\n") {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    Ok(read)
+}
+
+/// Read a message up to the lone `.`, `None` if it was bigger than `max_size`
+async fn read_data(
+    reader: &mut BufReader<OwnedReadHalf>,
+    max_size: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut data = vec![];
+    let mut line = vec![];
+    let mut too_large = false;
+    loop {
+        // Lines in the body may be longer than commands, but not by much
+        if read_line(reader, &mut line, MAX_LINE * 1000).await? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if line == b".\r\n" || line == b".\n" {
+            break;
+        }
+        if too_large {
+            continue;
+        }
+        let unstuffed = line.strip_prefix(b".").unwrap_or(&line);
+        data.extend_from_slice(unstuffed);
+        too_large = data.len() > max_size;
+    }
+    Ok(if too_large { None } else { Some(data) })
+}
+
+/// Find the app a `RCPT TO:<app_id+token@domain>` argument is for and check its token
+async fn recipient(db: &Database, argument: &str, domain: Option<&str>) -> Option<Recipient> {
+    let (app_id, token) = recipient_address(argument, domain)?;
+    let (app, user) = get_approved_app(db, app_id.parse().ok()?).await?;
+    if !token::authenticate(db, &app, Some(token)).await {
+        return None;
+    }
+    Some(Recipient { app, user })
+}
+
+/// Split a `RCPT TO:<app_id+token@domain>` argument into the app id and token
+fn recipient_address<'a>(argument: &'a str, domain: Option<&str>) -> Option<(&'a str, &'a str)> {
+    let address = argument
+        .split_once(':')?
+        .1
+        .trim()
+        .trim_start_matches('<')
+        .split('>')
+        .next()?;
+    let (local, address_domain) = address.rsplit_once('@')?;
+    if let Some(domain) = domain {
+        if !address_domain.eq_ignore_ascii_case(domain) {
+            return None;
+        }
+    }
+    local.split_once('+')
+}
+
+/// Turn a raw email into a message, with the subject as the title and the text as description
+fn parse_mail(data: &[u8]) -> Option<MessageData> {
+    let mail = MessageParser::default().parse(data)?;
+    let from = mail.from().and_then(|from| from.first());
+    let author = match from {
+        Some(from) => EmbedAuthor::new(
+            from.name().or_else(|| from.address()).unwrap_or_default(),
+            "",
+            "",
+        ),
+        None => EmbedAuthor::new("Email", "", ""),
+    };
+    let limit = max_attachment_size();
+    let mut total = 0;
+    let mut attachments = vec![];
+    let mut skipped = vec![];
+    for part in mail.attachments() {
+        let filename = part.attachment_name().unwrap_or("attachment").to_string();
+        let data = part.contents();
+        if total + data.len() > limit {
+            skipped.push(filename);
+            continue;
+        }
+        total += data.len();
+        attachments.push(Attachment {
+            filename,
+            data: data.to_vec(),
+        });
+    }
+    let fields = if skipped.is_empty() {
+        vec![]
+    } else {
+        vec![EmbedField::new("Too large to attach", &skipped.join("\n"), false)]
+    };
+    let mut embed = EmbedData::new(
+        mail.subject().unwrap_or("(no subject)"),
+        &summary(mail.body_text(0).as_deref()),
+        "",
+        GREY,
+        "Email",
+        author,
+        fields,
+    );
+    embed.timestamp = mail.date().map(|date| date.to_rfc3339());
+    Some(MessageData {
+        attachments,
+        ..MessageData::from_embeds(vec![embed])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::{mpsc::channel, RwLock};
+
+    /// Send a command and read the reply, returning the code of its last line
+    async fn command(
+        reader: &mut BufReader<OwnedReadHalf>,
+        writer: &mut OwnedWriteHalf,
+        line: &str,
+    ) -> String {
+        writer.write_all(format!("{line}\r\n").as_bytes()).await.unwrap();
+        read_reply(reader).await
+    }
+
+    async fn read_reply(reader: &mut BufReader<OwnedReadHalf>) -> String {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).await.unwrap();
+            // Multiline replies continue with `250-` and end with `250 `
+            if line.as_bytes().get(3) != Some(&b'-') {
+                return line[..3].to_string();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn session_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // The client connects lazily, and no command here looks anything up
+        let db = mongodb::Client::with_uri_str("mongodb://127.0.0.1:1")
+            .await
+            .unwrap()
+            .database("hookme");
+        let (sender, _receiver) = channel(1);
+        let state: SendEmbed = Arc::new(RwLock::new(sender));
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            session(stream, &state, &db).await
+        });
+
+        let (reader, mut writer) = TcpStream::connect(addr).await.unwrap().into_split();
+        let mut reader = BufReader::new(reader);
+        assert_eq!(read_reply(&mut reader).await, "220");
+        assert_eq!(command(&mut reader, &mut writer, "EHLO client").await, "250");
+        assert_eq!(command(&mut reader, &mut writer, "RCPT TO:<1+a@hookme>").await, "503");
+        assert_eq!(command(&mut reader, &mut writer, "MAIL FROM:<ci@example.com>").await, "250");
+        assert_eq!(command(&mut reader, &mut writer, "DATA").await, "503");
+        assert_eq!(command(&mut reader, &mut writer, "RCPT TO:<nobody@hookme>").await, "550");
+        assert_eq!(command(&mut reader, &mut writer, "NOOP").await, "250");
+        assert_eq!(command(&mut reader, &mut writer, "STARTTLS").await, "502");
+        assert_eq!(command(&mut reader, &mut writer, "QUIT").await, "221");
+        server.await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn recipient_addresses() {
+        assert_eq!(
+            recipient_address("TO:<42+token@hookme.example>", None),
+            Some(("42", "token"))
+        );
+        let domain = Some("hookme.example");
+        assert_eq!(
+            recipient_address("TO: <42+token@HookMe.Example> SIZE=100", domain),
+            Some(("42", "token"))
+        );
+        assert_eq!(recipient_address("TO:<42+token@other.example>", domain), None);
+        assert_eq!(recipient_address("TO:<42@hookme.example>", None), None);
+        assert_eq!(recipient_address("<42+token@hookme.example>", None), None);
+    }
+
+    #[test]
+    fn parse_mail_message() {
+        let mail = "From: CI Server <ci@example.com>\r\n\
+            Subject: Nightly build failed\r\n\
+            Date: Sat, 20 Jan 2024 10:00:00 +0000\r\n\
+            MIME-Version: 1.0\r\n\
+            Content-Type: multipart/mixed; boundary=\"b\"\r\n\
+            \r\n\
+            --b\r\n\
+            Content-Type: text/plain\r\n\
+            \r\n\
+            The build broke.\r\n\
+            --b\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Disposition: attachment; filename=\"build.log\"\r\n\
+            \r\n\
+            error: something\r\n\
+            --b--\r\n";
+        let message = parse_mail(mail.as_bytes()).unwrap();
+        let embed = &message.embeds[0];
+        assert_eq!(embed.title.as_deref(), Some("Nightly build failed"));
+        assert_eq!(embed.description.as_deref(), Some("The build broke."));
+        assert_eq!(embed.author.as_ref().unwrap().name, "CI Server");
+        assert!(embed.timestamp.is_some());
+        assert_eq!(message.attachments.len(), 1);
+        assert_eq!(message.attachments[0].filename, "build.log");
+    }
+
+    #[test]
+    fn parse_mail_without_subject() {
+        let message = parse_mail(b"From: ci@example.com\r\n\r\nHello\r\n").unwrap();
+        let embed = &message.embeds[0];
+        assert_eq!(embed.title.as_deref(), Some("(no subject)"));
+        assert_eq!(embed.author.as_ref().unwrap().name, "ci@example.com");
+    }
+}
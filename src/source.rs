@@ -0,0 +1,76 @@
+use crate::alertmanager::Alertmanager;
+use crate::body_type::Embed;
+use crate::drone::Drone;
+use crate::gitea::Gitea;
+use crate::github::Github;
+use crate::gitlab::Gitlab;
+use crate::grafana::Grafana;
+use crate::jenkins::Jenkins;
+use crate::registry::Registry;
+use crate::sentry::Sentry;
+use crate::slack::Slack;
+use crate::template::Json;
+use crate::AppCollection;
+use axum::http::HeaderMap;
+use std::sync::Arc;
+
+/// The result of parsing a request, payloads that shouldn't be posted give `Ok(None)`
+pub type Parsed = Result<Option<Box<dyn Embed + Send>>, serde_json::Error>;
+
+/// A webhook request as seen by a [`Source`]
+pub struct Incoming<'a> {
+    pub(crate) headers: &'a HeaderMap,
+    pub(crate) body: &'a [u8],
+    /// The token passed in the query string, if any
+    pub(crate) token: Option<&'a str>,
+}
+
+impl<'a> Incoming<'a> {
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+}
+
+/// A webhook format that can be turned into messages
+///
+/// Every registered source gets its own `/:app_id/<name>` route and takes part in
+/// detection on the generic `/:app_id` route
+pub trait Source: Send + Sync {
+    /// The route suffix for this source
+    fn name(&self) -> &'static str;
+
+    /// Whether a request looks like it was sent in this format
+    fn detect(&self, request: &Incoming) -> bool;
+
+    /// Check a signature the source sends itself, `None` leaves it to the app token
+    fn authenticate(&self, _app: &AppCollection, _request: &Incoming) -> Option<bool> { None }
+
+    /// Parse the body into something that can be posted
+    fn parse(&self, app: &AppCollection, request: &Incoming) -> Parsed;
+}
+
+/// Every source HookMe understands, in the order they are tried when detecting
+///
+/// Gitea also sends GitHub's headers, so it has to be checked first, Grafana's payload is a
+/// superset of Alertmanager's, and sources that are only detected by the shape of their body
+/// come last
+pub fn sources() -> Vec<Arc<dyn Source>> {
+    vec![
+        Arc::new(Gitea),
+        Arc::new(Github),
+        Arc::new(Gitlab),
+        Arc::new(Drone),
+        Arc::new(Sentry),
+        Arc::new(Grafana),
+        Arc::new(Alertmanager),
+        Arc::new(Registry),
+        Arc::new(Jenkins),
+        Arc::new(Slack),
+        Arc::new(Json),
+    ]
+}
+
+/// Find the source a request was sent from
+pub fn detect(request: &Incoming) -> Option<Arc<dyn Source>> {
+    sources().into_iter().find(|source| source.detect(request))
+}
@@ -0,0 +1,303 @@
+use crate::body_type::{non_empty, Embed, EmbedData, EmbedField, EmbedFooter, EmbedMedia};
+use crate::body_type::MessageData;
+use crate::source::{Incoming, Parsed, Source};
+use crate::AppCollection;
+use serde::de::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How an app turns an arbitrary JSON body into a message
+///
+/// Every string may contain `{{ $.path | filter:arg }}` placeholders, the path is a small
+/// subset of JSONPath supporting `.key`, `['key']`, `[0]` and `[*]`. The filters are
+/// `default:text`, `upper`, `lower`, `truncate:length`, `join:separator` and
+/// `map:from=to,from=to` which is mostly useful for picking a color
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EmbedTemplate {
+    pub(crate) content: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) description: Option<String>,
+    pub(crate) url: Option<String>,
+    /// Rendered and then read as a hex color such as `#ff0000`
+    pub(crate) color: Option<String>,
+    pub(crate) footer: Option<String>,
+    pub(crate) thumbnail: Option<String>,
+    pub(crate) image: Option<String>,
+    pub(crate) fields: Vec<FieldTemplate>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldTemplate {
+    pub(crate) name: String,
+    pub(crate) value: String,
+    #[serde(default)]
+    pub(crate) inline: bool,
+}
+
+impl EmbedTemplate {
+    /// Render the template against a JSON body
+    pub fn render(&self, body: &Value) -> MessageData {
+        let text = |template: &str| non_empty(render(template, body).trim());
+        let text_option = |template: &Option<String>| template.as_deref().and_then(text);
+        let fields: Vec<EmbedField> = self
+            .fields
+            .iter()
+            .filter_map(|field| {
+                // Fields without a value are left out, Discord rejects them
+                let value = text(&field.value)?;
+                let name = text(&field.name).unwrap_or_else(|| "\u{200b}".into());
+                Some(EmbedField::new(&name, &value, field.inline))
+            })
+            .collect();
+        let embed = EmbedData {
+            title: text_option(&self.title),
+            description: text_option(&self.description),
+            url: text_option(&self.url),
+            color: text_option(&self.color)
+                .and_then(|color| u32::from_str_radix(color.trim_start_matches('#'), 16).ok()),
+            footer: text_option(&self.footer).map(|text| EmbedFooter {
+                text,
+                icon_url: None,
+            }),
+            thumbnail: text_option(&self.thumbnail).map(|url| EmbedMedia::new(&url)),
+            image: text_option(&self.image).map(|url| EmbedMedia::new(&url)),
+            fields: if fields.is_empty() { None } else { Some(fields) },
+            ..Default::default()
+        };
+        let has_body = embed.title.is_some() || embed.description.is_some();
+        let embeds = if has_body || embed.fields.is_some() { vec![embed] } else { vec![] };
+        MessageData {
+            content: text_option(&self.content).unwrap_or_default(),
+            ..MessageData::from_embeds(embeds)
+        }
+    }
+}
+
+/// Replace every `{{ ... }}` placeholder in `template` with values from `body`
+pub fn render(template: &str, body: &Value) -> String {
+    let mut output = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        match rest[start + 2..].find("}}") {
+            Some(end) => {
+                let expression = &rest[start + 2..start + 2 + end];
+                output.push_str(&evaluate(expression, body));
+                rest = &rest[start + 2 + end + 2..];
+            }
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Evaluate a single `path | filter:arg | filter` expression
+fn evaluate(expression: &str, body: &Value) -> String {
+    let mut parts = expression.split('|').map(str::trim);
+    let path = parts.next().unwrap_or_default();
+    let values = select(body, path).into_iter().map(to_text);
+    let mut separator = ", ".to_string();
+    let mut filters = vec![];
+    for filter in parts {
+        let (name, arg) = filter.split_once(':').unwrap_or((filter, ""));
+        if name.trim() == "join" {
+            separator = arg.to_string();
+        } else {
+            filters.push((name.trim(), arg));
+        }
+    }
+    let mut text = values
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<String>>()
+        .join(&separator);
+    for (name, arg) in filters {
+        text = match name {
+            "default" if text.is_empty() => arg.to_string(),
+            "upper" => text.to_uppercase(),
+            "lower" => text.to_lowercase(),
+            "truncate" => match arg.trim().parse::<usize>() {
+                Ok(length) if text.chars().count() > length => {
+                    format!("{}...", text.chars().take(length).collect::<String>())
+                }
+                _ => text,
+            },
+            "map" => arg
+                .split(',')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(from, _)| from.trim() == text)
+                .map(|(_, to)| to.trim().to_string())
+                .unwrap_or(text),
+            _ => text,
+        };
+    }
+    text
+}
+
+/// Select values from `body` with a JSONPath style `path` such as `$.commits[*].id`
+pub fn select<'a>(body: &'a Value, path: &str) -> Vec<&'a Value> {
+    let path = path.trim();
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let mut current = vec![body];
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        let segment: String = match c {
+            '[' => chars.by_ref().take_while(|&c| c != ']').collect(),
+            // The leading dot is optional for the first key
+            c => {
+                let mut key = if c == '.' { String::new() } else { c.to_string() };
+                while let Some(&c) = chars.peek() {
+                    if c == '.' || c == '[' {
+                        break;
+                    }
+                    key.push(c);
+                    chars.next();
+                }
+                key
+            }
+        };
+        let segment = segment.trim().trim_matches(|c| c == '\'' || c == '"');
+        current = current
+            .into_iter()
+            .flat_map(|value| -> Vec<&Value> {
+                match (segment, value) {
+                    ("*", Value::Array(items)) => items.iter().collect(),
+                    ("*", Value::Object(map)) => map.values().collect(),
+                    (index, Value::Array(items)) => index
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|index| items.get(index))
+                        .into_iter()
+                        .collect(),
+                    (key, Value::Object(map)) => map.get(key).into_iter().collect(),
+                    _ => vec![],
+                }
+            })
+            .collect();
+    }
+    current
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => "".into(),
+        Value::String(string) => string.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// The rendered result of an apps template
+pub struct Templated {
+    message: MessageData,
+}
+
+impl Embed for Templated {
+    fn get_username(&self) -> String { "".into() }
+
+    fn get_avatar_url(&self) -> String { "".into() }
+
+    fn get_embeds(&self) -> Vec<EmbedData> { self.message.embeds.clone() }
+
+    fn get_message(&self) -> MessageData { self.message.clone() }
+}
+
+/// Any JSON body rendered through the apps template, authenticated with the app token
+pub struct Json;
+
+impl Source for Json {
+    fn name(&self) -> &'static str { "json" }
+
+    /// Templates are only used when asked for by route
+    fn detect(&self, _request: &Incoming) -> bool { false }
+
+    fn parse(&self, app: &AppCollection, request: &Incoming) -> Parsed {
+        let template = app
+            .template
+            .as_ref()
+            .ok_or_else(|| serde_json::Error::custom("the app has no template"))?;
+        let body: Value = serde_json::from_slice(request.body)?;
+        let message = template.render(&body);
+        if message.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Box::new(Templated { message })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, select};
+    use serde_json::{json, Value};
+
+    fn body() -> Value {
+        json!({
+            "status": "firing",
+            "repo": {"name": "hookme", "owner": {"login": "octo"}},
+            "dotted.key": "dots",
+            "commits": [
+                {"id": "a1", "message": "Fix"},
+                {"id": "b2", "message": "Add"},
+            ],
+            "tags": ["x", "y"],
+            "empty": [],
+            "count": 3,
+            "nothing": null,
+        })
+    }
+
+    #[test]
+    fn select_paths() {
+        let body = body();
+        assert_eq!(select(&body, "$.status"), vec![&json!("firing")]);
+        assert_eq!(select(&body, "status"), vec![&json!("firing")]);
+        assert_eq!(select(&body, "$['status']"), vec![&json!("firing")]);
+        assert_eq!(select(&body, "$[\"dotted.key\"]"), vec![&json!("dots")]);
+        assert_eq!(select(&body, "$.tags[1]"), vec![&json!("y")]);
+        assert_eq!(select(&body, "$.tags[2]"), Vec::<&Value>::new());
+        assert_eq!(select(&body, "$.commits[*].id"), vec![&json!("a1"), &json!("b2")]);
+        assert_eq!(select(&body, "$.repo.owner.login"), vec![&json!("octo")]);
+        assert_eq!(select(&body, "$.repo['owner'].login"), vec![&json!("octo")]);
+        assert_eq!(select(&body, "$.repo.missing.login"), Vec::<&Value>::new());
+        assert_eq!(select(&body, "$.status.length"), Vec::<&Value>::new());
+        assert_eq!(select(&body, "$"), vec![&body]);
+    }
+
+    #[test]
+    fn render_placeholders() {
+        let body = body();
+        assert_eq!(render("[{{ $.repo.name }}] {{$.status}}", &body), "[hookme] firing");
+        assert_eq!(render("{{ $.count }} commits", &body), "3 commits");
+        assert_eq!(render("{{ $.commits[*].message }}", &body), "Fix, Add");
+        assert_eq!(render("{{ $.nothing }}|{{ $.missing }}", &body), "|");
+        assert_eq!(render("no placeholders", &body), "no placeholders");
+        assert_eq!(render("left {{ $.status", &body), "left {{ $.status");
+        assert_eq!(render("{{ $.status }} and {{ $.repo", &body), "firing and {{ $.repo");
+    }
+
+    #[test]
+    fn filters() {
+        let body = body();
+        assert_eq!(render("{{ $.status | upper }}", &body), "FIRING");
+        assert_eq!(render("{{ $.repo.owner.login | upper | lower }}", &body), "octo");
+        assert_eq!(render("{{ $.status | truncate:4 }}", &body), "firi...");
+        assert_eq!(render("{{ $.status | truncate:10 }}", &body), "firing");
+        assert_eq!(render("{{ $.status | map:firing=🔥,resolved=✅ }}", &body), "🔥");
+        assert_eq!(render("{{ $.status | map:resolved=✅ }}", &body), "firing");
+        assert_eq!(render("{{ $.missing | default:none }}", &body), "none");
+        assert_eq!(render("{{ $.status | default:none }}", &body), "firing");
+        assert_eq!(render("{{ $.tags[*] | join:/ }}", &body), "x/y");
+        assert_eq!(render("{{ $.status | unknown }}", &body), "firing");
+    }
+
+    #[test]
+    fn join_with_default() {
+        let body = body();
+        assert_eq!(render("{{ $.tags[*] | join:/ | default:none }}", &body), "x/y");
+        assert_eq!(render("{{ $.empty[*] | join:/ | default:none }}", &body), "none");
+        assert_eq!(render("{{ $.empty[*] | default:none | join:/ }}", &body), "none");
+    }
+}
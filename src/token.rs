@@ -0,0 +1,180 @@
+use crate::{AppCollection, AppToken};
+use bcrypt::{hash, DEFAULT_COST};
+use futures_util::TryStreamExt;
+use hmac::{Hmac, Mac};
+use mongodb::bson::{doc, to_bson, DateTime, Document};
+use mongodb::Database;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use yyid::Yyid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The most verified tokens remembered before the cache starts over
+const MAX_CACHED: usize = 10_000;
+
+/// How stale a token's last use can get before it's written again, in milliseconds
+const LAST_USED_RESOLUTION: i64 = 60 * 1000;
+
+/// Tokens that have already passed bcrypt, so it only runs once per token
+///
+/// Tokens are keyed by an HMAC with a key that only lives in this process, so the cache never
+/// holds a usable token, and each entry keeps the hash it was checked against so a new token
+/// hash never matches an old entry
+struct Cache {
+    key: [u8; 32],
+    verified: Mutex<HashMap<(u64, [u8; 32]), String>>,
+}
+
+static CACHE: OnceLock<Cache> = OnceLock::new();
+
+fn cache() -> &'static Cache {
+    CACHE.get_or_init(|| Cache {
+        key: rand::random(),
+        verified: Mutex::new(HashMap::new()),
+    })
+}
+
+fn digest(key: &[u8], token: &str) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(token.as_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+/// Create a token, returning it along with the hashed copy that gets stored
+pub fn generate(name: &str, expires: Option<DateTime>) -> (String, AppToken) {
+    let token = Yyid::new().to_string();
+    let hashed = hash(token.as_bytes(), DEFAULT_COST).expect("FAILED TO HASH TOKEN");
+    let app_token = AppToken {
+        name: name.into(),
+        hash: hashed,
+        created: DateTime::now(),
+        last_used: None,
+        expires,
+    };
+    (token, app_token)
+}
+
+/// Find which of an app's live tokens a token is, running bcrypt on the blocking pool
+async fn find<'a>(app: &'a AppCollection, token: &str) -> Option<&'a AppToken> {
+    let live: Vec<&AppToken> = app.tokens.iter().filter(|t| !t.is_expired()).collect();
+    let cache = cache();
+    let entry = (app.app_id, digest(&cache.key, token));
+    let cached = cache
+        .verified
+        .lock()
+        .expect("Token cache poisoned")
+        .get(&entry)
+        .cloned();
+    if let Some(found) = cached.and_then(|cached| live.iter().find(|t| t.hash == cached)) {
+        return Some(found);
+    }
+    let check = {
+        let token = token.to_string();
+        let hashes: Vec<String> = live.iter().map(|t| t.hash.clone()).collect();
+        move || {
+            hashes
+                .iter()
+                .position(|hashed| bcrypt::verify(&token, hashed).unwrap_or(false))
+        }
+    };
+    let found = live[tokio::task::spawn_blocking(check).await.ok().flatten()?];
+    let mut cached = cache.verified.lock().expect("Token cache poisoned");
+    if cached.len() >= MAX_CACHED {
+        cached.clear();
+    }
+    cached.insert(entry, found.hash.clone());
+    Some(found)
+}
+
+/// Check a token against an app's tokens, logging and recording which one was used
+pub async fn authenticate(db: &Database, app: &AppCollection, token: Option<&str>) -> bool {
+    let found = match token {
+        Some(token) => find(app, token).await,
+        None => None,
+    };
+    let found = match found {
+        Some(found) => found,
+        None => return false,
+    };
+    println!("App {} authenticated with its {} token", app.app_id, found.name);
+    let now = DateTime::now();
+    let stale = found.last_used.is_none_or(|last_used| {
+        now.timestamp_millis() - last_used.timestamp_millis() > LAST_USED_RESOLUTION
+    });
+    if stale {
+        if let Err(e) = db
+            .collection::<AppCollection>("application")
+            .update_one(
+                doc! {"app_id": app.app_id as i64, "tokens.hash": &found.hash},
+                doc! {"$set": {"tokens.$.last_used": now}},
+                None,
+            )
+            .await
+        {
+            eprintln!("Failed to record use of app {}'s {} token: {e}", app.app_id, found.name);
+        }
+    }
+    true
+}
+
+/// Drop every cached token for an app, used when its tokens are revoked or replaced
+pub fn forget(app_id: u64) {
+    cache()
+        .verified
+        .lock()
+        .expect("Token cache poisoned")
+        .retain(|(cached_app, _), _| *cached_app != app_id);
+}
+
+/// Move apps that still have a single `token` hash over to a token named `default`
+///
+/// An old token still in its grace period after a rotation is kept until it expires
+pub async fn migrate(db: &Database) -> mongodb::error::Result<()> {
+    let apps = db.collection::<Document>("application");
+    let mut cursor = apps.find(doc! {"token": {"$exists": true}}, None).await?;
+    while let Some(app) = cursor.try_next().await? {
+        let id = match app.get_object_id("_id") {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        let created = id.timestamp();
+        let mut tokens = vec![];
+        if let Ok(hashed) = app.get_str("token") {
+            if !hashed.is_empty() {
+                tokens.push(AppToken {
+                    name: "default".into(),
+                    hash: hashed.into(),
+                    created,
+                    last_used: None,
+                    expires: None,
+                });
+            }
+        }
+        if let (Ok(hashed), Ok(expires)) = (
+            app.get_str("previous_token"),
+            app.get_datetime("previous_token_expires"),
+        ) {
+            if *expires > DateTime::now() {
+                tokens.push(AppToken {
+                    name: "default".into(),
+                    hash: hashed.into(),
+                    created,
+                    last_used: None,
+                    expires: Some(*expires),
+                });
+            }
+        }
+        apps.update_one(
+            doc! {"_id": id},
+            doc! {
+                "$set": {"tokens": to_bson(&tokens)?},
+                "$unset": {"token": "", "previous_token": "", "previous_token_expires": ""},
+            },
+            None,
+        )
+        .await?;
+    }
+    Ok(())
+}